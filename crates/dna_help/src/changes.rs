@@ -0,0 +1,106 @@
+use crate::{
+    get_header_time,
+    paging::{create_time_bucket_link, day_path, existing_day_buckets},
+    validation::entry_from_app_entry,
+    EntryAndHash,
+};
+use hdk3::prelude::*;
+use std::collections::HashSet;
+
+// Creates are already listed in the day paths for their type (see paging),
+// but updates and deletes can happen to entries of any age. So `crud!` also
+// links the entry touched by each update or delete from a day path under
+// `<type>_changes`, e.g. `goal_comment_changes_by_day.2026.10.18`, which lets a peer
+// find everything that changed since it was last online.
+
+pub fn changes_path(path: &str) -> String {
+    format!("{}_changes", path)
+}
+
+pub fn record_change(path: &str, entry_hash: EntryHash) -> ExternResult<()> {
+    create_time_bucket_link(&changes_path(path), entry_hash)
+}
+
+// the same shape as the data of the signals `crud!` sends for each action
+pub enum Change<T> {
+    Create(EntryAndHash<T>),
+    Update(EntryAndHash<T>),
+    Delete(HeaderHash),
+}
+
+// the entries listed in the day paths of `path` since the given time
+fn touched_entries(path: &str, since: f64, until: f64) -> ExternResult<Vec<EntryHash>> {
+    let mut entry_hashes = Vec::new();
+    for day in existing_day_buckets(path, since, until)? {
+        let bucket_hash = Path::from(day_path(path, day)).hash()?;
+        entry_hashes.extend(
+            get_links(bucket_hash, None)?
+                .into_inner()
+                .into_iter()
+                .map(|link| link.target),
+        );
+    }
+    Ok(entry_hashes)
+}
+
+// Every create, update and delete of the entries of one type after `since`,
+// each with the time it happened, in no particular order.
+pub fn fetch_changes_since<T: TryFrom<SerializedBytes, Error = SerializedBytesError> + Clone>(
+    path: &str,
+    since: f64,
+) -> ExternResult<Vec<(f64, Change<T>)>> {
+    let until = sys_time()?.as_secs_f64();
+    let mut seen: HashSet<EntryHash> = HashSet::new();
+    let mut changes = Vec::new();
+    let mut entry_hashes = touched_entries(path, since, until)?;
+    entry_hashes.extend(touched_entries(&changes_path(path), since, until)?);
+    for entry_hash in entry_hashes {
+        if !seen.insert(entry_hash.clone()) {
+            continue;
+        }
+        let details = match get_details(entry_hash.clone(), GetOptions::content())? {
+            Some(Details::Entry(details)) => details,
+            _ => continue,
+        };
+        let entry = match entry_from_app_entry::<T>(&details.entry) {
+            Some(entry) => entry,
+            None => continue,
+        };
+        for signed_header in details.headers.iter() {
+            let time = get_header_time(signed_header.header());
+            if time <= since {
+                continue;
+            }
+            // entries keep the address of their original header, even once updated
+            match signed_header.header() {
+                Header::Create(_) => changes.push((
+                    time,
+                    Change::Create((
+                        entry.clone(),
+                        signed_header.header_address().clone(),
+                        entry_hash.clone(),
+                    )),
+                )),
+                Header::Update(update) => changes.push((
+                    time,
+                    Change::Update((
+                        entry.clone(),
+                        update.original_header_address.clone(),
+                        entry_hash.clone(),
+                    )),
+                )),
+                _ => (),
+            }
+        }
+        for signed_header in details.deletes.iter() {
+            let time = get_header_time(signed_header.header());
+            if time <= since {
+                continue;
+            }
+            if let Header::Delete(delete) = signed_header.header() {
+                changes.push((time, Change::Delete(delete.deletes_address.clone())));
+            }
+        }
+    }
+    Ok(changes)
+}
@@ -2,8 +2,14 @@ use hdk3::prelude::*;
 pub use paste;
 use std::fmt;
 
+pub mod changes;
+pub mod paging;
+pub mod validation;
+
+pub use paging::{FetchPageInput, PageCursor};
+
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, SerializedBytes)]
-#[serde(from = "UIEnum")]
+#[serde(try_from = "UIEnum")]
 #[serde(into = "UIEnum")]
 pub enum ActionType {
     Create,
@@ -14,12 +20,15 @@ pub enum ActionType {
 #[derive(Debug, Serialize, Deserialize, SerializedBytes, Clone, PartialEq)]
 pub struct UIEnum(String);
 
-impl From<UIEnum> for ActionType {
-    fn from(ui_enum: UIEnum) -> Self {
+// unknown values are refused, so that a typo can't turn into a delete
+impl TryFrom<UIEnum> for ActionType {
+    type Error = String;
+    fn try_from(ui_enum: UIEnum) -> Result<Self, Self::Error> {
         match ui_enum.0.as_str() {
-            "create" => Self::Create,
-            "update" => Self::Update,
-            _ => Self::Delete,
+            "create" => Ok(Self::Create),
+            "update" => Ok(Self::Update),
+            "delete" => Ok(Self::Delete),
+            other => Err(format!("unknown action type {:?}", other)),
         }
     }
 }
@@ -36,6 +45,38 @@ impl fmt::Display for ActionType {
     }
 }
 
+/*
+  ERRORS
+*/
+
+// The errors our externs raise themselves, in both zomes. These reach the UI
+// as the message of a zome error, serialized like {"type":"NotFound","message":"..."}
+// so that it can branch on the type instead of on the wording. Errors from
+// the host, e.g. when a get or a create fails, are passed on as they are.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, SerializedBytes)]
+#[serde(tag = "type", content = "message")]
+pub enum AcornError {
+    NotFound(String),
+    Unauthorized(String),
+    InvalidHash(String),
+    ValidationFailed(String),
+}
+
+impl fmt::Display for AcornError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match serde_json::to_string(self) {
+            Ok(json) => write!(f, "{}", json),
+            Err(_) => write!(f, "{:?}", self),
+        }
+    }
+}
+
+impl From<AcornError> for HdkError {
+    fn from(acorn_error: AcornError) -> Self {
+        HdkError::Wasm(WasmError::Zome(acorn_error.to_string()))
+    }
+}
+
 pub type EntryAndHash<T> = (T, HeaderHash, EntryHash);
 pub type OptionEntryAndHash<T> = Option<EntryAndHash<T>>;
 
@@ -58,11 +99,11 @@ pub struct WrappedHeaderHash(pub HeaderHash);
 pub struct WrappedEntryHash(pub EntryHash);
 
 impl TryFrom<UIStringHash> for WrappedAgentPubKey {
-    type Error = String;
+    type Error = AcornError;
     fn try_from(ui_string_hash: UIStringHash) -> Result<Self, Self::Error> {
         match AgentPubKey::try_from(ui_string_hash.0) {
             Ok(address) => Ok(Self(address)),
-            Err(e) => Err(format!("{:?}", e)),
+            Err(e) => Err(AcornError::InvalidHash(format!("{:?}", e))),
         }
     }
 }
@@ -73,11 +114,11 @@ impl From<WrappedAgentPubKey> for UIStringHash {
 }
 
 impl TryFrom<UIStringHash> for WrappedHeaderHash {
-    type Error = String;
+    type Error = AcornError;
     fn try_from(ui_string_hash: UIStringHash) -> Result<Self, Self::Error> {
         match HeaderHash::try_from(ui_string_hash.0) {
             Ok(address) => Ok(Self(address)),
-            Err(e) => Err(format!("{:?}", e)),
+            Err(e) => Err(AcornError::InvalidHash(format!("{:?}", e))),
         }
     }
 }
@@ -88,11 +129,11 @@ impl From<WrappedHeaderHash> for UIStringHash {
 }
 
 impl TryFrom<UIStringHash> for WrappedEntryHash {
-    type Error = String;
+    type Error = AcornError;
     fn try_from(ui_string_hash: UIStringHash) -> Result<Self, Self::Error> {
         match EntryHash::try_from(ui_string_hash.0) {
             Ok(address) => Ok(Self(address)),
-            Err(e) => Err(format!("{:?}", e)),
+            Err(e) => Err(AcornError::InvalidHash(format!("{:?}", e))),
         }
     }
 }
@@ -140,6 +181,12 @@ pub fn get_header_hash(shh: element::SignedHeaderHashed) -> HeaderHash {
     shh.header_hashed().as_hash().to_owned()
 }
 
+// the time a header was written, as a unix timestamp in seconds
+pub fn get_header_time(header: &Header) -> f64 {
+    let timestamp = header.timestamp();
+    timestamp.0 as f64 + timestamp.1 as f64 / 1_000_000_000.0
+}
+
 pub fn get_latest_for_entry<T: TryFrom<SerializedBytes, Error = SerializedBytesError>>(
     entry_hash: EntryHash,
     get_options: GetOptions
@@ -195,7 +242,18 @@ pub fn fetch_links<
     entry_hash: EntryHash,
     get_options: GetOptions
 ) -> Result<Vec<WireEntry>, HdkError> {
-    Ok(get_links(entry_hash, None)?
+    fetch_links_with_tag::<EntryType, WireEntry>(entry_hash, None, get_options)
+}
+
+pub fn fetch_links_with_tag<
+    EntryType: TryFrom<SerializedBytes, Error = SerializedBytesError>,
+    WireEntry: From<EntryAndHash<EntryType>>,
+>(
+    entry_hash: EntryHash,
+    link_tag: Option<LinkTag>,
+    get_options: GetOptions
+) -> Result<Vec<WireEntry>, HdkError> {
+    Ok(get_links(entry_hash, link_tag)?
         .into_inner()
         .into_iter()
         .map(|link: link::Link| get_latest_for_entry::<EntryType>(link.target.clone(), get_options.clone()))
@@ -205,10 +263,74 @@ pub fn fetch_links<
         .collect())
 }
 
+/*
+  INDEXES
+*/
+
+// the Path under which we link every entry that points at
+// a particular header, e.g. all the votes, comments and edges of one goal.
+// entries of different types are told apart by the link tag.
+// it has a root of its own, e.g. `goal_index`, so that the paths
+// aren't linked from the root every entry of that type is linked from
+pub fn index_path(index: &str, address: &WrappedHeaderHash) -> Path {
+    Path::from(format!("{}_index.{}", index, address.0))
+}
+
+pub fn create_index_link(
+    index: &str,
+    address: &WrappedHeaderHash,
+    entry_hash: EntryHash,
+    link_tag: &str,
+) -> ExternResult<()> {
+    let path = index_path(index, address);
+    path.ensure()?;
+    create_link(path.hash()?, entry_hash, LinkTag::new(link_tag))?;
+    Ok(())
+}
+
+// like create_index_link, but only if the entry isn't linked already.
+// gives back whether a link was made
+pub fn ensure_index_link(
+    index: &str,
+    address: &WrappedHeaderHash,
+    entry_hash: EntryHash,
+    link_tag: &str,
+) -> ExternResult<bool> {
+    let path_hash = index_path(index, address).hash()?;
+    let linked = get_links(path_hash, Some(LinkTag::new(link_tag)))?
+        .into_inner()
+        .iter()
+        .any(|link| link.target == entry_hash);
+    if linked {
+        return Ok(false);
+    }
+    create_index_link(index, address, entry_hash, link_tag)?;
+    Ok(true)
+}
+
+// whether anything of any type is linked from the index for the address
+pub fn has_index_links(index: &str, address: &WrappedHeaderHash) -> ExternResult<bool> {
+    let path_hash = index_path(index, address).hash()?;
+    Ok(!get_links(path_hash, None)?.into_inner().is_empty())
+}
+
+pub fn fetch_index_links<
+    EntryType: TryFrom<SerializedBytes, Error = SerializedBytesError>,
+    WireEntry: From<EntryAndHash<EntryType>>,
+>(
+    index: &str,
+    address: &WrappedHeaderHash,
+    link_tag: &str,
+    get_options: GetOptions
+) -> Result<Vec<WireEntry>, HdkError> {
+    let path_hash = index_path(index, address).hash()?;
+    fetch_links_with_tag::<EntryType, WireEntry>(path_hash, Some(LinkTag::new(link_tag)), get_options)
+}
+
 #[macro_export]
 macro_rules! crud {
     (
-      $crud_type:ident, $i:ident, $path:expr, $get_peers:ident, $convert_to_receiver_signal:ident
+      @impl $crud_type:ident, $i:ident, $path:expr, $get_peers:ident, $convert_to_receiver_signal:ident
     ) => {
 
         $crate::paste::paste! {
@@ -259,10 +381,23 @@ macro_rules! crud {
           #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, SerializedBytes)]
           pub struct [<Vec $crud_type WireEntry>](pub Vec<[<$crud_type WireEntry>]>);
 
+          #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, SerializedBytes)]
+          pub struct [<$crud_type Page>] {
+            pub entries: Vec<[<$crud_type WireEntry>]>,
+            pub next_cursor: Option<$crate::PageCursor>,
+          }
+
           /*
             CREATE
           */
           pub fn [<inner_create_ $i>](entry: $crud_type, send_signal: bool) -> ExternResult<[<$crud_type WireEntry>]> {
+            [<inner_check_create_ $i>](&entry)?;
+            [<inner_create_ $i _unchecked>](entry, send_signal)
+          }
+
+          // for callers which have already done their own checks, and know
+          // that the usual ones would refuse the entry for the wrong reason
+          pub fn [<inner_create_ $i _unchecked>](entry: $crud_type, send_signal: bool) -> ExternResult<[<$crud_type WireEntry>]> {
             let address = create_entry(&entry)?;
             let entry_hash = hash_entry(&entry)?;
             let path = Path::from([<$i:upper _PATH>]);
@@ -273,6 +408,8 @@ macro_rules! crud {
             debug!("end! of Path.ensure() time {:?}", end_ensure_time.clone());
             let path_hash = path.hash()?;
             create_link(path_hash, entry_hash.clone(), ())?;
+            $crate::paging::create_time_bucket_link([<$i:upper _PATH>], entry_hash.clone())?;
+            [<inner_index_ $i>](&entry, &entry_hash)?;
             let wire_entry = [<$crud_type WireEntry>] {
               entry,
               address: $crate::WrappedHeaderHash(address),
@@ -313,12 +450,24 @@ macro_rules! crud {
             [<inner_fetch_ $i s>](GetOptions::latest())
           }
 
+          pub fn [<inner_fetch_ $i s_page>](input: $crate::FetchPageInput, get_options: GetOptions) -> ExternResult<[<$crud_type Page>]> {
+            let (entries, next_cursor) = $crate::paging::fetch_links_page::<$crud_type, [<$crud_type WireEntry>]>([<$i:upper _PATH>], input, get_options)?;
+            Ok([<$crud_type Page>] { entries, next_cursor })
+          }
+
+          #[hdk_extern]
+          pub fn [<fetch_ $i s_page>](input: $crate::FetchPageInput) -> ExternResult<[<$crud_type Page>]> {
+            [<inner_fetch_ $i s_page>](input, GetOptions::latest())
+          }
+
           /*
             UPDATE
           */
           pub fn [<inner_update_ $i>](update: [<$crud_type UpdateInput>], send_signal: bool) -> ExternResult<[<$crud_type WireEntry>]> {
+            [<inner_check_change_ $i>](&update.address)?;
             update_entry(update.address.0.clone(), &update.entry)?;
             let entry_address = hash_entry(&update.entry)?;
+            $crate::changes::record_change([<$i:upper _PATH>], entry_address.clone())?;
             let wire_entry = [<$crud_type WireEntry>] {
                 entry: update.entry,
                 address: update.address,
@@ -345,6 +494,13 @@ macro_rules! crud {
             DELETE
           */
           pub fn [<inner_archive_ $i>](address: $crate::WrappedHeaderHash, send_signal: bool) -> ExternResult<$crate::WrappedHeaderHash> {
+            [<inner_check_change_ $i>](&address)?;
+            // the delete is found again through the entry it deletes
+            if let Some(element) = get(address.0.clone(), GetOptions::content())? {
+              if let Some(entry_hash) = element.header().entry_hash() {
+                $crate::changes::record_change([<$i:upper _PATH>], entry_hash.clone())?;
+              }
+            }
             delete_entry(address.0.clone())?;
             if (send_signal) {
               let signal = $convert_to_receiver_signal([<$crud_type Signal>] {
@@ -362,14 +518,228 @@ macro_rules! crud {
           pub fn [<archive_ $i>](address: $crate::WrappedHeaderHash) -> ExternResult<$crate::WrappedHeaderHash> {
             [<inner_archive_ $i>](address, true)
           }
+
+          /*
+            CHANGES
+          */
+          // every create, update and delete since the given time, each as the
+          // signal that was sent for it, along with the time it happened
+          pub fn [<inner_fetch_ $i _changes_since>](since: f64) -> ExternResult<Vec<(f64, [<$crud_type Signal>])>> {
+            let changes = $crate::changes::fetch_changes_since::<$crud_type>([<$i:upper _PATH>], since)?;
+            Ok(changes.into_iter().map(|(time, change)| {
+              let (action, data) = match change {
+                $crate::changes::Change::Create(entry_and_hash) => ($crate::ActionType::Create, [<$crud_type SignalData>]::Create(entry_and_hash.into())),
+                $crate::changes::Change::Update(entry_and_hash) => ($crate::ActionType::Update, [<$crud_type SignalData>]::Update(entry_and_hash.into())),
+                $crate::changes::Change::Delete(address) => ($crate::ActionType::Delete, [<$crud_type SignalData>]::Delete($crate::WrappedHeaderHash(address))),
+              };
+              (time, [<$crud_type Signal>] {
+                entry_type: $path.to_string(),
+                action,
+                data,
+              })
+            }).collect())
+          }
         }
     };
+    (
+      @index $crud_type:ident, $i:ident, $path:expr
+    ) => {
+        $crate::paste::paste! {
+          // nothing to index for this entry type
+          fn [<inner_index_ $i>](_entry: &$crud_type, _entry_hash: &EntryHash) -> ExternResult<()> {
+            Ok(())
+          }
+        }
+    };
+    (
+      @index $crud_type:ident, $i:ident, $path:expr, $index:ident: [$($field:ident),+]
+    ) => {
+        $crate::paste::paste! {
+          fn [<inner_index_ $i>](entry: &$crud_type, entry_hash: &EntryHash) -> ExternResult<()> {
+            $(
+              $crate::create_index_link(stringify!($index), &entry.$field, entry_hash.clone(), $path)?;
+            )+
+            Ok(())
+          }
+
+          pub fn [<inner_fetch_ $i s_for_ $index>](address: $crate::WrappedHeaderHash, get_options: GetOptions) -> ExternResult<[<Vec $crud_type WireEntry>]> {
+            let entries = $crate::fetch_index_links::<$crud_type, [<$crud_type WireEntry>]>(stringify!($index), &address, $path, get_options)?;
+            Ok([<Vec $crud_type WireEntry>](entries))
+          }
+
+          #[hdk_extern]
+          pub fn [<fetch_ $i s_for_ $index>](address: $crate::WrappedHeaderHash) -> ExternResult<[<Vec $crud_type WireEntry>]> {
+            [<inner_fetch_ $i s_for_ $index>](address, GetOptions::latest())
+          }
+
+          // link every entry of this type from its index, for the ones made
+          // before they were indexed. gives back how many links were made
+          pub fn [<inner_reindex_ $i s>]() -> ExternResult<u32> {
+            let mut added = 0;
+            let path_hash = Path::from([<$i:upper _PATH>]).hash()?;
+            for link in get_links(path_hash, None)?.into_inner() {
+              // links to paths are also found here, and aren't entries of this type
+              let entry = match get(link.target.clone(), GetOptions::content())? {
+                Some(element) => match element.entry().to_app_option::<$crud_type>() {
+                  Ok(Some(entry)) => entry,
+                  _ => continue,
+                },
+                None => continue,
+              };
+              $(
+                if $crate::ensure_index_link(stringify!($index), &entry.$field, link.target.clone(), $path)? {
+                  added += 1;
+                }
+              )+
+            }
+            Ok(added)
+          }
+        }
+    };
+    (
+      @check_create $crud_type:ident, $i:ident
+    ) => {
+        $crate::paste::paste! {
+          // nothing to check before creating this entry type
+          fn [<inner_check_create_ $i>](_entry: &$crud_type) -> ExternResult<()> {
+            Ok(())
+          }
+        }
+    };
+    (
+      @check_create $crud_type:ident, $i:ident, $check_create:ident
+    ) => {
+        $crate::paste::paste! {
+          fn [<inner_check_create_ $i>](entry: &$crud_type) -> ExternResult<()> {
+            $check_create(entry)
+          }
+        }
+    };
+    (
+      @check_change $crud_type:ident, $i:ident
+    ) => {
+        $crate::paste::paste! {
+          // nothing to check before updating or archiving this entry type
+          fn [<inner_check_change_ $i>](_address: &$crate::WrappedHeaderHash) -> ExternResult<()> {
+            Ok(())
+          }
+        }
+    };
+    (
+      @check_change $crud_type:ident, $i:ident, $check_change:ident
+    ) => {
+        $crate::paste::paste! {
+          fn [<inner_check_change_ $i>](address: &$crate::WrappedHeaderHash) -> ExternResult<()> {
+            $check_change(address)
+          }
+        }
+    };
+    (
+      $crud_type:ident, $i:ident, $path:expr, $get_peers:ident, $convert_to_receiver_signal:ident
+    ) => {
+        $crate::crud!(@impl $crud_type, $i, $path, $get_peers, $convert_to_receiver_signal);
+        $crate::crud!(@index $crud_type, $i, $path);
+        $crate::crud!(@check_create $crud_type, $i);
+        $crate::crud!(@check_change $crud_type, $i);
+    };
+    // the same as above, but also calls the given function with the address
+    // of the entry before it is updated or archived, so that can be refused
+    (
+      $crud_type:ident, $i:ident, $path:expr, $get_peers:ident, $convert_to_receiver_signal:ident,
+      check_change: $check_change:ident
+    ) => {
+        $crate::crud!(@impl $crud_type, $i, $path, $get_peers, $convert_to_receiver_signal);
+        $crate::crud!(@index $crud_type, $i, $path);
+        $crate::crud!(@check_create $crud_type, $i);
+        $crate::crud!(@check_change $crud_type, $i, $check_change);
+    };
+    // the same as above, but also calls the given functions with each new
+    // entry before it is created, and with the address of one before it is
+    // updated or archived, so that either can be refused with an error
+    (
+      $crud_type:ident, $i:ident, $path:expr, $get_peers:ident, $convert_to_receiver_signal:ident,
+      check_create: $check_create:ident,
+      check_change: $check_change:ident
+    ) => {
+        $crate::crud!(@impl $crud_type, $i, $path, $get_peers, $convert_to_receiver_signal);
+        $crate::crud!(@index $crud_type, $i, $path);
+        $crate::crud!(@check_create $crud_type, $i, $check_create);
+        $crate::crud!(@check_change $crud_type, $i, $check_change);
+    };
+    // the same as the first, but also links each entry from the index of every
+    // header address held in the given fields, e.g. `goal: [goal_address]`,
+    // and adds a fetch for all the entries in one of those indexes
+    (
+      $crud_type:ident, $i:ident, $path:expr, $get_peers:ident, $convert_to_receiver_signal:ident,
+      $index:ident: [$($field:ident),+]
+    ) => {
+        $crate::crud!(@impl $crud_type, $i, $path, $get_peers, $convert_to_receiver_signal);
+        $crate::crud!(@index $crud_type, $i, $path, $index: [$($field),+]);
+        $crate::crud!(@check_create $crud_type, $i);
+        $crate::crud!(@check_change $crud_type, $i);
+    };
+    // the same as above, but with a check before each create
+    (
+      $crud_type:ident, $i:ident, $path:expr, $get_peers:ident, $convert_to_receiver_signal:ident,
+      $index:ident: [$($field:ident),+],
+      check_create: $check_create:ident
+    ) => {
+        $crate::crud!(@impl $crud_type, $i, $path, $get_peers, $convert_to_receiver_signal);
+        $crate::crud!(@index $crud_type, $i, $path, $index: [$($field),+]);
+        $crate::crud!(@check_create $crud_type, $i, $check_create);
+        $crate::crud!(@check_change $crud_type, $i);
+    };
+    // the same as above, but with a check before each update or archive
+    (
+      $crud_type:ident, $i:ident, $path:expr, $get_peers:ident, $convert_to_receiver_signal:ident,
+      $index:ident: [$($field:ident),+],
+      check_change: $check_change:ident
+    ) => {
+        $crate::crud!(@impl $crud_type, $i, $path, $get_peers, $convert_to_receiver_signal);
+        $crate::crud!(@index $crud_type, $i, $path, $index: [$($field),+]);
+        $crate::crud!(@check_create $crud_type, $i);
+        $crate::crud!(@check_change $crud_type, $i, $check_change);
+    };
 }
 
 #[cfg(test)]
 mod tests {
+    use super::*;
+
     #[test]
     fn it_works() {
         assert_eq!(2 + 2, 4);
     }
+
+    #[test]
+    fn action_types_must_be_known() {
+        let parse = |s: &str| ActionType::try_from(UIEnum(s.into()));
+        assert_eq!(parse("update"), Ok(ActionType::Update));
+        assert_eq!(parse("delete"), Ok(ActionType::Delete));
+        assert!(parse("updte").is_err());
+        assert!(parse("Update").is_err());
+        // and the same goes for deserializing them
+        let sb = SerializedBytes::try_from(UIEnum("updte".into())).unwrap();
+        assert!(ActionType::try_from(sb).is_err());
+    }
+
+    #[test]
+    fn index_paths_have_a_root_of_their_own() {
+        let address = WrappedHeaderHash(HeaderHash::from_raw_36(vec![1; 36]));
+        assert_eq!(
+            index_path("goal", &address),
+            Path::from(vec![
+                Component::from("goal_index"),
+                Component::from(address.0.to_string()),
+            ])
+        );
+    }
+
+    #[test]
+    fn acorn_error_displays_its_type() {
+        assert_eq!(
+            AcornError::NotFound("no project meta exists".into()).to_string(),
+            r#"{"type":"NotFound","message":"no project meta exists"}"#
+        );
+    }
 }
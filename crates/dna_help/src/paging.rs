@@ -0,0 +1,254 @@
+use crate::{get_latest_for_entry, EntryAndHash, WrappedEntryHash};
+use hdk3::prelude::*;
+
+// Besides the path for its type, every entry made through `crud!` is also
+// linked from a path for the day it was made, e.g. `goal_comment_by_day.2026.10.18`,
+// so that it can be fetched a page at a time, within a window of time.
+// The days have a root of their own, so that they aren't linked from
+// the path every entry of the type is linked from.
+
+// nothing was bucketed before this year, so paging starts here
+// when the caller doesn't ask for a start time
+const FIRST_BUCKET_YEAR: i64 = 2020;
+const SECONDS_PER_DAY: i64 = 86_400;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, SerializedBytes)]
+pub struct PageCursor {
+    pub timestamp: f64,
+    pub entry_address: WrappedEntryHash,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, SerializedBytes)]
+pub struct FetchPageInput {
+    // pass back the next_cursor of the previous page to continue from it
+    pub cursor: Option<PageCursor>,
+    pub limit: u32,
+    // unix timestamps in seconds, both inclusive
+    pub since: Option<f64>,
+    pub until: Option<f64>,
+}
+
+/*
+  CALENDAR
+  these only do arithmetic, so they can be unit tested without a conductor
+*/
+
+// the (year, month, day) of a number of days since 1970-01-01
+// see http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+pub fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = (if z >= 0 { z } else { z - 146_096 }) / 146_097;
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = (if mp < 10 { mp + 3 } else { mp - 9 }) as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+// the number of days since 1970-01-01 of a (year, month, day)
+pub fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = (if year >= 0 { year } else { year - 399 }) / 400;
+    let yoe = year - era * 400;
+    let month = month as i64;
+    let doy = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day as i64 - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+pub fn day_of_timestamp(timestamp: f64) -> i64 {
+    (timestamp / SECONDS_PER_DAY as f64).floor() as i64
+}
+
+pub fn year_path(path: &str, year: i64) -> String {
+    format!("{}_by_day.{}", path, year)
+}
+
+pub fn month_path(path: &str, year: i64, month: u32) -> String {
+    format!("{}_by_day.{}.{:02}", path, year, month)
+}
+
+pub fn day_path(path: &str, day: i64) -> String {
+    let (year, month, day) = civil_from_days(day);
+    format!("{}_by_day.{}.{:02}.{:02}", path, year, month, day)
+}
+
+// whether `(timestamp, entry_hash)` comes after the cursor in page order
+fn is_after_cursor(cursor: &Option<PageCursor>, timestamp: f64, entry_hash: &EntryHash) -> bool {
+    match cursor {
+        Some(cursor) => {
+            timestamp > cursor.timestamp
+                || (timestamp == cursor.timestamp
+                    && entry_hash.to_string() > cursor.entry_address.0.to_string())
+        }
+        None => true,
+    }
+}
+
+/*
+  DHT
+*/
+
+// the time a link was made, as a unix timestamp in seconds,
+// which for the bucket links is the time the entry was made
+pub fn get_link_time(link: &link::Link) -> f64 {
+    link.timestamp.0 as f64 + link.timestamp.1 as f64 / 1_000_000_000.0
+}
+
+// The bucket is picked before the link is made, so a link made just after
+// midnight can have a time on the day after its bucket. Links are never
+// earlier than their bucket, so looking one day further back finds them.
+pub fn first_bucket_day(since: f64) -> i64 {
+    day_of_timestamp(since) - 1
+}
+
+pub fn create_time_bucket_link(path: &str, entry_hash: EntryHash) -> ExternResult<()> {
+    let now = sys_time()?.as_secs_f64();
+    let bucket = Path::from(day_path(path, day_of_timestamp(now)));
+    bucket.ensure()?;
+    create_link(bucket.hash()?, entry_hash, ())?;
+    Ok(())
+}
+
+// The day buckets which could hold entries between since and until, oldest first.
+// Whole years and months that were never written to are skipped
+// without looking at each of their days.
+pub(crate) fn existing_day_buckets(path: &str, since: f64, until: f64) -> ExternResult<Vec<i64>> {
+    let first_day = first_bucket_day(since);
+    let last_day = day_of_timestamp(until);
+    let (first_year, _, _) = civil_from_days(first_day);
+    let (last_year, _, _) = civil_from_days(last_day);
+    let mut days = Vec::new();
+    for year in first_year..=last_year {
+        if !Path::from(year_path(path, year)).exists()? {
+            continue;
+        }
+        for month in 1..=12 {
+            let month_start = days_from_civil(year, month, 1);
+            let next_month_start = if month == 12 {
+                days_from_civil(year + 1, 1, 1)
+            } else {
+                days_from_civil(year, month + 1, 1)
+            };
+            if next_month_start <= first_day || month_start > last_day {
+                continue;
+            }
+            if !Path::from(month_path(path, year, month)).exists()? {
+                continue;
+            }
+            days.extend(month_start.max(first_day)..next_month_start.min(last_day + 1));
+        }
+    }
+    Ok(days)
+}
+
+// one page of the entries made between since and until, oldest first,
+// along with the cursor to pass in to get the next page, if there is one
+pub fn fetch_links_page<
+    EntryType: TryFrom<SerializedBytes, Error = SerializedBytesError>,
+    WireEntry: From<EntryAndHash<EntryType>>,
+>(
+    path: &str,
+    input: FetchPageInput,
+    get_options: GetOptions,
+) -> ExternResult<(Vec<WireEntry>, Option<PageCursor>)> {
+    let until = match input.until {
+        Some(until) => until,
+        None => sys_time()?.as_secs_f64(),
+    };
+    let mut since = match input.since {
+        Some(since) => since,
+        None => (days_from_civil(FIRST_BUCKET_YEAR, 1, 1) * SECONDS_PER_DAY) as f64,
+    };
+    if let Some(cursor) = &input.cursor {
+        since = since.max(cursor.timestamp);
+    }
+
+    let limit = input.limit as usize;
+    let mut entries: Vec<WireEntry> = Vec::new();
+    let mut last_seen: Option<PageCursor> = None;
+    if limit == 0 || since > until {
+        return Ok((entries, None));
+    }
+    for day in existing_day_buckets(path, since, until)? {
+        let bucket_hash = Path::from(day_path(path, day)).hash()?;
+        let mut links: Vec<(f64, EntryHash)> = get_links(bucket_hash, None)?
+            .into_inner()
+            .into_iter()
+            .map(|link| (get_link_time(&link), link.target))
+            .filter(|(timestamp, entry_hash)| {
+                *timestamp >= since
+                    && *timestamp <= until
+                    && is_after_cursor(&input.cursor, *timestamp, entry_hash)
+            })
+            .collect();
+        links.sort_by(|a, b| {
+            a.0.partial_cmp(&b.0)
+                .unwrap_or(std::cmp::Ordering::Equal)
+                .then_with(|| a.1.to_string().cmp(&b.1.to_string()))
+        });
+        for (timestamp, entry_hash) in links {
+            if entries.len() == limit {
+                // there is more to come, so pick up after the last one we returned
+                return Ok((entries, last_seen));
+            }
+            // deleted entries are skipped over, but still move the cursor along
+            if let Some(entry_and_hash) =
+                get_latest_for_entry::<EntryType>(entry_hash.clone(), get_options.clone())?
+            {
+                entries.push(WireEntry::from(entry_and_hash));
+            }
+            last_seen = Some(PageCursor {
+                timestamp,
+                entry_address: WrappedEntryHash(entry_hash),
+            });
+        }
+    }
+    Ok((entries, None))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn civil_days_round_trip() {
+        assert_eq!(civil_from_days(0), (1970, 1, 1));
+        assert_eq!(days_from_civil(1970, 1, 1), 0);
+        // a leap day
+        let leap_day = days_from_civil(2020, 2, 29);
+        assert_eq!(civil_from_days(leap_day), (2020, 2, 29));
+        assert_eq!(civil_from_days(leap_day + 1), (2020, 3, 1));
+        for day in days_from_civil(2019, 12, 1)..days_from_civil(2027, 1, 1) {
+            let (year, month, day_of_month) = civil_from_days(day);
+            assert_eq!(days_from_civil(year, month, day_of_month), day);
+        }
+    }
+
+    #[test]
+    fn day_paths() {
+        // 2026-10-18T09:16:24Z
+        let day = day_of_timestamp(1_792_314_984.0);
+        assert_eq!(
+            day_path("goal_comment", day),
+            "goal_comment_by_day.2026.10.18"
+        );
+        assert_eq!(
+            month_path("goal_comment", 2026, 3),
+            "goal_comment_by_day.2026.03"
+        );
+        assert_eq!(year_path("goal_comment", 2026), "goal_comment_by_day.2026");
+    }
+
+    #[test]
+    fn buckets_start_the_day_before() {
+        // a link timestamped just after midnight, in the bucket of the day before
+        let midnight = (days_from_civil(2026, 10, 18) * SECONDS_PER_DAY) as f64;
+        let bucket = day_of_timestamp(midnight - 0.001);
+        assert!(first_bucket_day(midnight) <= bucket);
+        assert_eq!(first_bucket_day(midnight), days_from_civil(2026, 10, 17));
+    }
+}
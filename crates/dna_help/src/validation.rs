@@ -0,0 +1,305 @@
+use crate::WrappedAgentPubKey;
+use hdk3::prelude::*;
+
+/*
+  RULES
+  these only look at the values they are given, so they can be
+  unit tested without a conductor
+*/
+
+// keep the first result which isn't Valid, or Valid if they all pass
+pub fn all_valid(results: Vec<ValidateCallbackResult>) -> ValidateCallbackResult {
+    results
+        .into_iter()
+        .find(|result| *result != ValidateCallbackResult::Valid)
+        .unwrap_or(ValidateCallbackResult::Valid)
+}
+
+pub fn must_be_author(
+    author: &AgentPubKey,
+    claimed: &WrappedAgentPubKey,
+    field: &str,
+) -> ValidateCallbackResult {
+    if &claimed.0 == author {
+        ValidateCallbackResult::Valid
+    } else {
+        ValidateCallbackResult::Invalid(format!("{} must match the author of the entry", field))
+    }
+}
+
+pub fn must_be_in_range(value: f64, min: f64, max: f64, field: &str) -> ValidateCallbackResult {
+    // NaN is never contained, so it is rejected too
+    if (min..=max).contains(&value) {
+        ValidateCallbackResult::Valid
+    } else {
+        ValidateCallbackResult::Invalid(format!(
+            "{} must be between {} and {}, got {}",
+            field, min, max, value
+        ))
+    }
+}
+
+pub fn must_not_be_empty(value: &str, field: &str) -> ValidateCallbackResult {
+    if value.trim().is_empty() {
+        ValidateCallbackResult::Invalid(format!("{} must not be empty", field))
+    } else {
+        ValidateCallbackResult::Valid
+    }
+}
+
+// lengths are counted in characters, not bytes
+pub fn must_not_be_longer_than(value: &str, max: usize, field: &str) -> ValidateCallbackResult {
+    let length = value.chars().count();
+    if length <= max {
+        ValidateCallbackResult::Valid
+    } else {
+        ValidateCallbackResult::Invalid(format!(
+            "{} can be at most {} characters, got {}",
+            field, max, length
+        ))
+    }
+}
+
+/*
+  CALLBACK HELPERS
+  these unpack the ValidateData handed to a validate_*_entry_* callback
+*/
+
+fn wrong_entry_type() -> ValidateCallbackResult {
+    ValidateCallbackResult::Invalid("element does not contain the expected entry type".into())
+}
+
+// get the entry held by an element, as long as it is of the expected type
+pub fn entry_from_element<T: TryFrom<SerializedBytes, Error = SerializedBytesError>>(
+    element: &Element,
+) -> ExternResult<Option<T>> {
+    Ok(element.entry().to_app_option::<T>()?)
+}
+
+// get the entry held in the base or target of a link, as long as it is of the expected type
+pub fn entry_from_app_entry<T: TryFrom<SerializedBytes, Error = SerializedBytesError>>(
+    entry: &Entry,
+) -> Option<T> {
+    match entry {
+        Entry::App(app_entry_bytes) => T::try_from(app_entry_bytes.clone().into_sb()).ok(),
+        _ => None,
+    }
+}
+
+// validate the entry being created or updated, along with its author
+pub fn validate_entry_with<T, F>(
+    validate_data: ValidateData,
+    rule: F,
+) -> ExternResult<ValidateCallbackResult>
+where
+    T: TryFrom<SerializedBytes, Error = SerializedBytesError>,
+    F: FnOnce(T, &AgentPubKey) -> ExternResult<ValidateCallbackResult>,
+{
+    let element = validate_data.element;
+    let author = element.header().author().clone();
+    match entry_from_element::<T>(&element)? {
+        Some(entry) => rule(entry, &author),
+        None => Ok(wrong_entry_type()),
+    }
+}
+
+// validate an update, given the new entry, the entry it replaces, and its author
+pub fn validate_update_with<T, F>(
+    validate_data: ValidateData,
+    rule: F,
+) -> ExternResult<ValidateCallbackResult>
+where
+    T: TryFrom<SerializedBytes, Error = SerializedBytesError>,
+    F: FnOnce(T, T, &AgentPubKey) -> ExternResult<ValidateCallbackResult>,
+{
+    let element = validate_data.element;
+    let author = element.header().author().clone();
+    let original_header_address = match element.header() {
+        Header::Update(update) => update.original_header_address.clone(),
+        _ => {
+            return Ok(ValidateCallbackResult::Invalid(
+                "expected an update header".into(),
+            ))
+        }
+    };
+    let new_entry = match entry_from_element::<T>(&element)? {
+        Some(entry) => entry,
+        None => return Ok(wrong_entry_type()),
+    };
+    match get(original_header_address.clone(), GetOptions::content())? {
+        Some(original) => match entry_from_element::<T>(&original)? {
+            Some(original_entry) => rule(new_entry, original_entry, &author),
+            None => Ok(wrong_entry_type()),
+        },
+        None => Ok(ValidateCallbackResult::UnresolvedDependencies(vec![
+            original_header_address.into(),
+        ])),
+    }
+}
+
+// validate a delete, given the entry being deleted, and the author of the delete
+pub fn validate_delete_with<T, F>(
+    validate_data: ValidateData,
+    rule: F,
+) -> ExternResult<ValidateCallbackResult>
+where
+    T: TryFrom<SerializedBytes, Error = SerializedBytesError>,
+    F: FnOnce(T, &AgentPubKey) -> ExternResult<ValidateCallbackResult>,
+{
+    let element = validate_data.element;
+    let author = element.header().author().clone();
+    let deletes_address = match element.header() {
+        Header::Delete(delete) => delete.deletes_address.clone(),
+        _ => {
+            return Ok(ValidateCallbackResult::Invalid(
+                "expected a delete header".into(),
+            ))
+        }
+    };
+    match get(deletes_address.clone(), GetOptions::content())? {
+        Some(deleted) => match entry_from_element::<T>(&deleted)? {
+            Some(deleted_entry) => rule(deleted_entry, &author),
+            None => Ok(wrong_entry_type()),
+        },
+        None => Ok(ValidateCallbackResult::UnresolvedDependencies(vec![
+            deletes_address.into(),
+        ])),
+    }
+}
+
+// check that a header address points at an entry of type T which hasn't been deleted
+pub fn must_be_live_entry<T: TryFrom<SerializedBytes, Error = SerializedBytesError>>(
+    address: &HeaderHash,
+    field: &str,
+) -> ExternResult<ValidateCallbackResult> {
+    match get_details(address.clone(), GetOptions::content())? {
+        Some(Details::Element(details)) => {
+            if !details.deletes.is_empty() {
+                Ok(ValidateCallbackResult::Invalid(format!(
+                    "{} points at an entry which was deleted",
+                    field
+                )))
+            } else {
+                match entry_from_element::<T>(&details.element)? {
+                    Some(_) => Ok(ValidateCallbackResult::Valid),
+                    None => Ok(ValidateCallbackResult::Invalid(format!(
+                        "{} points at an entry of the wrong type",
+                        field
+                    ))),
+                }
+            }
+        }
+        _ => Ok(ValidateCallbackResult::UnresolvedDependencies(vec![
+            address.clone().into(),
+        ])),
+    }
+}
+
+// Check that a header address points at an entry of type T, whether or not
+// it has been deleted since. Unlike must_be_live_entry, this only depends on
+// the header, so every peer comes to the same answer.
+pub fn must_be_entry<T: TryFrom<SerializedBytes, Error = SerializedBytesError>>(
+    address: &HeaderHash,
+    field: &str,
+) -> ExternResult<ValidateCallbackResult> {
+    match get(address.clone(), GetOptions::content())? {
+        // anything else on the DHT doesn't deserialize as a T
+        Some(element) => match entry_from_element::<T>(&element).ok().flatten() {
+            Some(_) => Ok(ValidateCallbackResult::Valid),
+            None => Ok(ValidateCallbackResult::Invalid(format!(
+                "{} points at an entry of the wrong type",
+                field
+            ))),
+        },
+        None => Ok(ValidateCallbackResult::UnresolvedDependencies(vec![
+            address.clone().into(),
+        ])),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn agent(byte: u8) -> AgentPubKey {
+        AgentPubKey::from_raw_36(vec![byte; 36])
+    }
+
+    #[test]
+    fn all_valid_keeps_first_failure() {
+        assert_eq!(all_valid(vec![]), ValidateCallbackResult::Valid);
+        assert_eq!(
+            all_valid(vec![
+                ValidateCallbackResult::Valid,
+                ValidateCallbackResult::Invalid("first".into()),
+                ValidateCallbackResult::Invalid("second".into()),
+            ]),
+            ValidateCallbackResult::Invalid("first".into())
+        );
+    }
+
+    #[test]
+    fn length_is_counted_in_characters() {
+        assert_eq!(
+            must_not_be_longer_than("héllo", 5, "bio"),
+            ValidateCallbackResult::Valid
+        );
+        assert_ne!(
+            must_not_be_longer_than("hello!", 5, "bio"),
+            ValidateCallbackResult::Valid
+        );
+    }
+
+    #[test]
+    fn author_must_match() {
+        let author = agent(1);
+        assert_eq!(
+            must_be_author(&author, &WrappedAgentPubKey(agent(1)), "agent_address"),
+            ValidateCallbackResult::Valid
+        );
+        assert_ne!(
+            must_be_author(&author, &WrappedAgentPubKey(agent(2)), "agent_address"),
+            ValidateCallbackResult::Valid
+        );
+    }
+
+    #[test]
+    fn range_is_inclusive_and_rejects_nan() {
+        assert_eq!(
+            must_be_in_range(0.0, 0.0, 1.0, "x"),
+            ValidateCallbackResult::Valid
+        );
+        assert_eq!(
+            must_be_in_range(1.0, 0.0, 1.0, "x"),
+            ValidateCallbackResult::Valid
+        );
+        assert_ne!(
+            must_be_in_range(1.01, 0.0, 1.0, "x"),
+            ValidateCallbackResult::Valid
+        );
+        assert_ne!(
+            must_be_in_range(-0.1, 0.0, 1.0, "x"),
+            ValidateCallbackResult::Valid
+        );
+        assert_ne!(
+            must_be_in_range(f64::NAN, 0.0, 1.0, "x"),
+            ValidateCallbackResult::Valid
+        );
+    }
+
+    #[test]
+    fn whitespace_is_empty() {
+        assert_eq!(
+            must_not_be_empty("hi", "content"),
+            ValidateCallbackResult::Valid
+        );
+        assert_ne!(
+            must_not_be_empty("   ", "content"),
+            ValidateCallbackResult::Valid
+        );
+        assert_ne!(
+            must_not_be_empty("", "content"),
+            ValidateCallbackResult::Valid
+        );
+    }
+}
@@ -3,7 +3,11 @@ use hdk3::prelude::*;
 
 mod profile;
 
-use profile::{Profile, AGENTS_PATH};
+use profile::{
+    avatar::{AvatarChunk, FileManifest},
+    presence::LastSeen,
+    Profile, AGENTS_PATH,
+};
 
 #[hdk_extern]
 fn init(_: ()) -> ExternResult<InitCallbackResult> {
@@ -12,4 +16,10 @@ fn init(_: ()) -> ExternResult<InitCallbackResult> {
     Ok(InitCallbackResult::Pass)
 }
 
-entry_defs![Path::entry_def(), Profile::entry_def()];
+entry_defs![
+    Path::entry_def(),
+    Profile::entry_def(),
+    LastSeen::entry_def(),
+    AvatarChunk::entry_def(),
+    FileManifest::entry_def()
+];
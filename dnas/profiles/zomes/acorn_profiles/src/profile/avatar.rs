@@ -0,0 +1,435 @@
+use dna_help::{
+    paging::get_link_time,
+    validation::{
+        all_valid, entry_from_element, must_be_author, validate_delete_with, validate_entry_with,
+    },
+    AcornError, WrappedAgentPubKey, WrappedEntryHash, WrappedHeaderHash,
+};
+use hdk3::prelude::*;
+
+/*
+  AVATARS
+  Avatars are kept on the DHT instead of at an image host, split into chunks
+  small enough to gossip, with a manifest listing them in order. Each agent's
+  current avatar is linked from a path of their own, `avatars.<agent>`.
+*/
+
+pub const AVATARS_PATH: &str = "avatars";
+pub const AVATAR_TAG: &str = "avatar";
+pub const MAX_AVATAR_SIZE: usize = 512 * 1024;
+pub const CHUNK_SIZE: usize = 64 * 1024;
+pub const AVATAR_MIME_TYPES: [&str; 4] = ["image/png", "image/jpeg", "image/gif", "image/webp"];
+
+#[hdk_entry(id = "avatar_chunk")]
+#[derive(Debug, Clone, PartialEq)]
+pub struct AvatarChunk {
+    #[serde(with = "serde_bytes")]
+    pub bytes: Vec<u8>,
+}
+
+#[hdk_entry(id = "file_manifest")]
+#[derive(Debug, Clone, PartialEq)]
+pub struct FileManifest {
+    pub agent_address: WrappedAgentPubKey,
+    pub mime_type: String,
+    pub size: u32,
+    pub chunks: Vec<WrappedEntryHash>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, SerializedBytes)]
+pub struct FileManifestWireEntry {
+    pub entry: FileManifest,
+    pub address: WrappedHeaderHash,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, SerializedBytes)]
+pub struct UploadAvatarInput {
+    #[serde(with = "serde_bytes")]
+    pub bytes: Vec<u8>,
+    pub mime_type: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, SerializedBytes)]
+pub struct Avatar {
+    #[serde(with = "serde_bytes")]
+    pub bytes: Vec<u8>,
+    pub mime_type: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, SerializedBytes)]
+pub struct FetchAvatarOutput(Option<Avatar>);
+
+pub fn avatar_path(agent_pubkey: &AgentPubKey) -> Path {
+    Path::from(vec![
+        Component::from(AVATARS_PATH),
+        Component::from(agent_pubkey.to_string().as_str()),
+    ])
+}
+
+// whether the bytes start the way files of that type do
+fn looks_like(mime_type: &str, bytes: &[u8]) -> bool {
+    match mime_type {
+        "image/png" => bytes.starts_with(&[0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A]),
+        "image/jpeg" => bytes.starts_with(&[0xFF, 0xD8, 0xFF]),
+        "image/gif" => bytes.starts_with(b"GIF87a") || bytes.starts_with(b"GIF89a"),
+        "image/webp" => bytes.len() >= 12 && &bytes[..4] == b"RIFF" && &bytes[8..12] == b"WEBP",
+        _ => false,
+    }
+}
+
+pub fn check_avatar(mime_type: &str, bytes: &[u8]) -> Result<(), String> {
+    if !AVATAR_MIME_TYPES.contains(&mime_type) {
+        return Err(format!(
+            "avatars can be one of {}, not {}",
+            AVATAR_MIME_TYPES.join(", "),
+            mime_type
+        ));
+    }
+    if bytes.is_empty() || bytes.len() > MAX_AVATAR_SIZE {
+        return Err(format!(
+            "avatars have to be between 1 and {} bytes, and this is {}",
+            MAX_AVATAR_SIZE,
+            bytes.len()
+        ));
+    }
+    if !looks_like(mime_type, bytes) {
+        return Err(format!("the avatar is not a {} file", mime_type));
+    }
+    Ok(())
+}
+
+fn fetch_avatar_manifests(
+    agent_pubkey: &AgentPubKey,
+) -> ExternResult<Vec<(link::Link, FileManifest)>> {
+    let path_hash = avatar_path(agent_pubkey).hash()?;
+    let mut manifests = Vec::new();
+    for link in get_links(path_hash, Some(LinkTag::new(AVATAR_TAG)))?.into_inner() {
+        if let Some(element) = get(link.target.clone(), GetOptions::content())? {
+            // link validation only lets avatar links point at manifests
+            if let Some(manifest) = entry_from_element::<FileManifest>(&element)? {
+                manifests.push((link, manifest));
+            }
+        }
+    }
+    Ok(manifests)
+}
+
+#[hdk_extern]
+pub fn upload_avatar(input: UploadAvatarInput) -> ExternResult<FileManifestWireEntry> {
+    check_avatar(&input.mime_type, &input.bytes).map_err(AcornError::ValidationFailed)?;
+    let agent_pubkey = agent_info()?.agent_initial_pubkey;
+    let mut chunks = Vec::new();
+    for bytes in input.bytes.chunks(CHUNK_SIZE) {
+        let chunk = AvatarChunk {
+            bytes: bytes.to_vec(),
+        };
+        create_entry(&chunk)?;
+        chunks.push(WrappedEntryHash(hash_entry(&chunk)?));
+    }
+    let manifest = FileManifest {
+        agent_address: WrappedAgentPubKey(agent_pubkey.clone()),
+        mime_type: input.mime_type,
+        size: input.bytes.len() as u32,
+        chunks,
+    };
+    let address = create_entry(&manifest)?;
+    // the new avatar takes the place of the old one
+    remove_avatar(&agent_pubkey)?;
+    let path = avatar_path(&agent_pubkey);
+    path.ensure()?;
+    create_link(
+        path.hash()?,
+        hash_entry(&manifest)?,
+        LinkTag::new(AVATAR_TAG),
+    )?;
+    Ok(FileManifestWireEntry {
+        entry: manifest,
+        address: WrappedHeaderHash(address),
+    })
+}
+
+// unlink an agent's avatar, so that fetch_avatar no longer finds one
+pub fn remove_avatar(agent_pubkey: &AgentPubKey) -> ExternResult<()> {
+    for (link, _) in fetch_avatar_manifests(agent_pubkey)? {
+        delete_link(link.create_link_hash)?;
+    }
+    Ok(())
+}
+
+// the headers of the entries of one type which the agent created on their
+// own source chain, leaving out the ones they have deleted since
+fn query_my_creates(entry_def_index: EntryDefIndex) -> ExternResult<Vec<HeaderHash>> {
+    let entry_type = EntryType::App(AppEntryType::new(
+        entry_def_index,
+        zome_info()?.zome_id,
+        EntryVisibility::Public,
+    ));
+    let deleted: Vec<HeaderHash> = query(QueryFilter::new().header_type(HeaderType::Delete))?
+        .0
+        .iter()
+        .filter_map(|element| match element.header() {
+            Header::Delete(delete) => Some(delete.deletes_address.clone()),
+            _ => None,
+        })
+        .collect();
+    Ok(query(
+        QueryFilter::new()
+            .entry_type(entry_type)
+            .header_type(HeaderType::Create),
+    )?
+    .0
+    .iter()
+    .map(|element| element.header_address().clone())
+    .filter(|header_address| !deleted.contains(header_address))
+    .collect())
+}
+
+// Unlink the agent's avatar, and delete every manifest and chunk they
+// uploaded, including those of avatars they replaced. They are all on the
+// agent's own source chain, so this has to be called by the agent.
+pub fn delete_avatars(agent_pubkey: &AgentPubKey) -> ExternResult<()> {
+    remove_avatar(agent_pubkey)?;
+    for header_address in query_my_creates(entry_def_index!(FileManifest)?)? {
+        delete_entry(header_address)?;
+    }
+    for header_address in query_my_creates(entry_def_index!(AvatarChunk)?)? {
+        delete_entry(header_address)?;
+    }
+    Ok(())
+}
+
+fn fetch_chunk(chunk_hash: &WrappedEntryHash) -> ExternResult<Option<AvatarChunk>> {
+    match get(chunk_hash.0.clone(), GetOptions::content())? {
+        Some(element) => Ok(entry_from_element::<AvatarChunk>(&element).ok().flatten()),
+        None => Ok(None),
+    }
+}
+
+// put the chunks of a manifest back together, or give the first one which can't be found
+fn fetch_bytes(manifest: &FileManifest) -> ExternResult<Result<Vec<u8>, EntryHash>> {
+    let mut bytes = Vec::with_capacity(manifest.size as usize);
+    for chunk_hash in manifest.chunks.iter() {
+        match fetch_chunk(chunk_hash)? {
+            Some(chunk) => bytes.extend(chunk.bytes),
+            None => return Ok(Err(chunk_hash.0.clone())),
+        }
+    }
+    Ok(Ok(bytes))
+}
+
+#[hdk_extern]
+pub fn fetch_avatar(agent_address: WrappedAgentPubKey) -> ExternResult<FetchAvatarOutput> {
+    // if an old link wasn't removed, the newest one wins
+    let latest = fetch_avatar_manifests(&agent_address.0)?
+        .into_iter()
+        .max_by(|(a, _), (b, _)| {
+            get_link_time(a)
+                .partial_cmp(&get_link_time(b))
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
+    let manifest = match latest {
+        Some((_, manifest)) => manifest,
+        None => return Ok(FetchAvatarOutput(None)),
+    };
+    let avatar = fetch_bytes(&manifest)?.ok().map(|bytes| Avatar {
+        bytes,
+        mime_type: manifest.mime_type,
+    });
+    Ok(FetchAvatarOutput(avatar))
+}
+
+/*
+VALIDATION
+*/
+
+fn check_avatar_chunk(chunk: &AvatarChunk) -> ValidateCallbackResult {
+    if chunk.bytes.is_empty() || chunk.bytes.len() > CHUNK_SIZE {
+        ValidateCallbackResult::Invalid(format!(
+            "avatar chunks have to be between 1 and {} bytes",
+            CHUNK_SIZE
+        ))
+    } else {
+        ValidateCallbackResult::Valid
+    }
+}
+
+#[hdk_extern]
+pub fn validate_create_entry_avatar_chunk(
+    validate_data: ValidateData,
+) -> ExternResult<ValidateCallbackResult> {
+    validate_entry_with(validate_data, |chunk: AvatarChunk, _: &AgentPubKey| {
+        Ok(check_avatar_chunk(&chunk))
+    })
+}
+
+// a new avatar is uploaded instead, since the manifest names its chunks by hash
+#[hdk_extern]
+pub fn validate_update_entry_avatar_chunk(_: ValidateData) -> ExternResult<ValidateCallbackResult> {
+    Ok(ValidateCallbackResult::Invalid(
+        "avatar chunks can't be updated".into(),
+    ))
+}
+
+// only whoever uploaded a chunk can delete it
+#[hdk_extern]
+pub fn validate_delete_entry_avatar_chunk(
+    validate_data: ValidateData,
+) -> ExternResult<ValidateCallbackResult> {
+    let author = validate_data.element.header().author().clone();
+    let deletes_address = match validate_data.element.header() {
+        Header::Delete(delete) => delete.deletes_address.clone(),
+        _ => {
+            return Ok(ValidateCallbackResult::Invalid(
+                "expected a delete header".into(),
+            ))
+        }
+    };
+    match get(deletes_address.clone(), GetOptions::content())? {
+        Some(deleted) if deleted.header().author() == &author => Ok(ValidateCallbackResult::Valid),
+        Some(_) => Ok(ValidateCallbackResult::Invalid(
+            "only whoever uploaded an avatar chunk can delete it".into(),
+        )),
+        None => Ok(ValidateCallbackResult::UnresolvedDependencies(vec![
+            deletes_address.into(),
+        ])),
+    }
+}
+
+// the manifest has to describe an avatar which passes check_avatar,
+// which means fetching its chunks
+fn check_manifest(manifest: &FileManifest) -> ExternResult<ValidateCallbackResult> {
+    let expected_chunks = (manifest.size as usize + CHUNK_SIZE - 1) / CHUNK_SIZE;
+    if manifest.chunks.len() != expected_chunks {
+        return Ok(ValidateCallbackResult::Invalid(format!(
+            "an avatar of {} bytes has {} chunks, not {}",
+            manifest.size,
+            expected_chunks,
+            manifest.chunks.len()
+        )));
+    }
+    let bytes = match fetch_bytes(manifest)? {
+        Ok(bytes) => bytes,
+        Err(missing) => {
+            return Ok(ValidateCallbackResult::UnresolvedDependencies(vec![
+                missing.into(),
+            ]))
+        }
+    };
+    if bytes.len() != manifest.size as usize {
+        return Ok(ValidateCallbackResult::Invalid(
+            "the avatar chunks don't add up to its size".into(),
+        ));
+    }
+    Ok(match check_avatar(&manifest.mime_type, &bytes) {
+        Ok(()) => ValidateCallbackResult::Valid,
+        Err(reason) => ValidateCallbackResult::Invalid(reason),
+    })
+}
+
+#[hdk_extern]
+pub fn validate_create_entry_file_manifest(
+    validate_data: ValidateData,
+) -> ExternResult<ValidateCallbackResult> {
+    validate_entry_with(
+        validate_data,
+        |manifest: FileManifest, author: &AgentPubKey| {
+            Ok(all_valid(vec![
+                must_be_author(author, &manifest.agent_address, "agent_address"),
+                check_manifest(&manifest)?,
+            ]))
+        },
+    )
+}
+
+// a new avatar is uploaded instead, and linked in place of the old one
+#[hdk_extern]
+pub fn validate_update_entry_file_manifest(
+    _: ValidateData,
+) -> ExternResult<ValidateCallbackResult> {
+    Ok(ValidateCallbackResult::Invalid(
+        "file manifests can't be updated".into(),
+    ))
+}
+
+#[hdk_extern]
+pub fn validate_delete_entry_file_manifest(
+    validate_data: ValidateData,
+) -> ExternResult<ValidateCallbackResult> {
+    validate_delete_with(
+        validate_data,
+        |manifest: FileManifest, author: &AgentPubKey| {
+            Ok(must_be_author(
+                author,
+                &manifest.agent_address,
+                "agent_address",
+            ))
+        },
+    )
+}
+
+// the avatar link has to come from the author's own avatar path,
+// and point at a manifest of theirs
+pub fn must_be_own_avatar(
+    base: &Entry,
+    manifest: &FileManifest,
+    author: &AgentPubKey,
+) -> ExternResult<ValidateLinkCallbackResult> {
+    if &manifest.agent_address.0 != author {
+        return Ok(ValidateLinkCallbackResult::Invalid(
+            "cannot link to other people's avatar".into(),
+        ));
+    }
+    if hash_entry(base)? != avatar_path(author).hash()? {
+        return Ok(ValidateLinkCallbackResult::Invalid(
+            "an avatar can only be linked from its agent's avatar path".into(),
+        ));
+    }
+    Ok(ValidateLinkCallbackResult::Valid)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PNG: [u8; 8] = [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+
+    #[test]
+    fn avatars_must_be_a_known_image_type() {
+        assert!(check_avatar("image/png", &PNG).is_ok());
+        assert!(check_avatar("image/gif", b"GIF89a...").is_ok());
+        assert!(check_avatar("image/svg+xml", b"<svg></svg>").is_err());
+    }
+
+    #[test]
+    fn avatars_must_match_their_type() {
+        assert!(check_avatar("image/jpeg", &PNG).is_err());
+        assert!(check_avatar("image/webp", b"RIFF\0\0\0\0WEBPVP8 ").is_ok());
+        assert!(check_avatar("image/webp", b"RIFF\0\0\0\0WAVE").is_err());
+    }
+
+    #[test]
+    fn avatars_have_a_size_limit() {
+        assert!(check_avatar("image/png", &[]).is_err());
+        let mut too_big = PNG.to_vec();
+        too_big.resize(MAX_AVATAR_SIZE + 1, 0);
+        assert!(check_avatar("image/png", &too_big).is_err());
+        too_big.truncate(MAX_AVATAR_SIZE);
+        assert!(check_avatar("image/png", &too_big).is_ok());
+    }
+
+    #[test]
+    fn chunks_have_a_size_limit() {
+        let chunk = |size| AvatarChunk {
+            bytes: vec![0; size],
+        };
+        assert_eq!(
+            check_avatar_chunk(&chunk(CHUNK_SIZE)),
+            ValidateCallbackResult::Valid
+        );
+        assert_ne!(
+            check_avatar_chunk(&chunk(CHUNK_SIZE + 1)),
+            ValidateCallbackResult::Valid
+        );
+        assert_ne!(check_avatar_chunk(&chunk(0)), ValidateCallbackResult::Valid);
+    }
+}
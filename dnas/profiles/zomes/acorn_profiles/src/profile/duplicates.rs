@@ -0,0 +1,181 @@
+use super::{
+    handle_path, search::unindex_profile, update_whoami, Profile, WhoAmIOutput, WireEntry,
+    AGENTS_PATH, HANDLE_TAG,
+};
+use dna_help::{get_header_time, get_latest_for_entry, validation::entry_from_element};
+use hdk3::prelude::*;
+use std::cmp::Ordering;
+
+/*
+  ONE PROFILE PER AGENT
+  Validation refuses a second profile from an agent who still has one, by
+  looking back through their source chain. Agents who made more than one
+  before that was refused can collapse them with `collapse_duplicate_profiles`.
+  Until they do, every peer settles on the same one: the one made first,
+  with ties broken by header address.
+*/
+
+pub fn rank_profiles(a: (f64, &HeaderHash), b: (f64, &HeaderHash)) -> Ordering {
+    a.0.partial_cmp(&b.0)
+        .unwrap_or(Ordering::Equal)
+        .then_with(|| a.1.to_string().cmp(&b.1.to_string()))
+}
+
+// a profile linked from an agent, with what's needed to rank and remove it
+pub struct ProfileCandidate {
+    pub created_at: f64,
+    pub wire_entry: WireEntry,
+    // the entry the links point at, which is the one first created
+    pub linked_entry_hash: EntryHash,
+}
+
+// every live profile linked from the agent, in rank order
+pub fn fetch_profile_candidates(agent_pubkey: AgentPubKey) -> ExternResult<Vec<ProfileCandidate>> {
+    let mut candidates: Vec<ProfileCandidate> = Vec::new();
+    for link in get_links(EntryHash::from(agent_pubkey), None)?.into_inner() {
+        let entry_and_hash =
+            match get_latest_for_entry::<Profile>(link.target.clone(), GetOptions::content())? {
+                Some(entry_and_hash) => entry_and_hash,
+                None => continue,
+            };
+        // the same profile can be linked more than once
+        if candidates
+            .iter()
+            .any(|candidate| candidate.wire_entry.address.0 == entry_and_hash.1)
+        {
+            continue;
+        }
+        if let Some(element) = get(entry_and_hash.1.clone(), GetOptions::content())? {
+            candidates.push(ProfileCandidate {
+                created_at: get_header_time(element.header()),
+                wire_entry: WireEntry::from(entry_and_hash),
+                linked_entry_hash: link.target,
+            });
+        }
+    }
+    candidates.sort_by(|a, b| {
+        rank_profiles(
+            (a.created_at, &a.wire_entry.address.0),
+            (b.created_at, &b.wire_entry.address.0),
+        )
+    });
+    Ok(candidates)
+}
+
+// Refuses a profile from an agent whose source chain already holds
+// one which hasn't been deleted. Needs the author's whole chain.
+pub fn must_be_first_profile(
+    element: &Element,
+    validation_package: Option<ValidationPackage>,
+) -> ValidateCallbackResult {
+    let elements = match validation_package {
+        Some(validation_package) => validation_package.0,
+        None => {
+            return ValidateCallbackResult::Invalid(
+                "the author's source chain is needed to check for other profiles".into(),
+            )
+        }
+    };
+    let header_seq = element.header().header_seq();
+    let earlier = elements
+        .iter()
+        .filter(|other| other.header().header_seq() < header_seq);
+    let mut profiles: Vec<HeaderHash> = Vec::new();
+    for other in earlier {
+        match other.header() {
+            Header::Create(_) => {
+                if let Ok(Some(_)) = entry_from_element::<Profile>(other) {
+                    profiles.push(other.header_address().clone());
+                }
+            }
+            Header::Delete(delete) => {
+                profiles.retain(|profile| profile != &delete.deletes_address);
+            }
+            _ => {}
+        }
+    }
+    if profiles.is_empty() {
+        ValidateCallbackResult::Valid
+    } else {
+        ValidateCallbackResult::Invalid("an agent can only have one profile".into())
+    }
+}
+
+pub fn delete_links_to(
+    base: EntryHash,
+    tag: Option<LinkTag>,
+    target: &EntryHash,
+) -> ExternResult<()> {
+    for link in get_links(base, tag)?.into_inner() {
+        if &link.target == target {
+            delete_link(link.create_link_hash)?;
+        }
+    }
+    Ok(())
+}
+
+// Keeps the first of my profiles, and deletes the rest along with their links.
+// The one kept takes on the content that whoami used to return, which
+// was from the profile linked last, since that's the one that was being edited.
+#[hdk_extern]
+pub fn collapse_duplicate_profiles(_: ()) -> ExternResult<WhoAmIOutput> {
+    let agent_pubkey = agent_info()?.agent_initial_pubkey;
+    let mut candidates = fetch_profile_candidates(agent_pubkey.clone())?;
+    if candidates.len() < 2 {
+        return Ok(WhoAmIOutput(
+            candidates.pop().map(|candidate| candidate.wire_entry),
+        ));
+    }
+    let last_linked = get_links(EntryHash::from(agent_pubkey.clone()), None)?
+        .into_inner()
+        .pop()
+        .map(|link| link.target);
+    let content = candidates
+        .iter()
+        .find(|candidate| Some(&candidate.linked_entry_hash) == last_linked.as_ref())
+        .map(|candidate| candidate.wire_entry.entry.clone());
+    let kept = candidates.remove(0);
+    for duplicate in candidates {
+        let target = duplicate.linked_entry_hash;
+        delete_links_to(EntryHash::from(agent_pubkey.clone()), None, &target)?;
+        delete_links_to(Path::from(AGENTS_PATH).hash()?, None, &target)?;
+        delete_links_to(
+            handle_path(&duplicate.wire_entry.entry.handle).hash()?,
+            Some(LinkTag::new(HANDLE_TAG)),
+            &target,
+        )?;
+        unindex_profile(&duplicate.wire_entry.entry)?;
+        delete_entry(duplicate.wire_entry.address.0)?;
+    }
+    match content {
+        Some(content) if content != kept.wire_entry.entry => {
+            let updated = update_whoami(WireEntry {
+                entry: content,
+                address: kept.wire_entry.address,
+            })?;
+            Ok(WhoAmIOutput(Some(updated)))
+        }
+        _ => Ok(WhoAmIOutput(Some(kept.wire_entry))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header(byte: u8) -> HeaderHash {
+        HeaderHash::from_raw_36(vec![byte; 36])
+    }
+
+    #[test]
+    fn earliest_profile_ranks_first() {
+        assert_eq!(
+            rank_profiles((1.0, &header(2)), (2.0, &header(1))),
+            Ordering::Less
+        );
+        let (a, b) = (header(1), header(2));
+        let expected = a.to_string().cmp(&b.to_string());
+        assert_eq!(rank_profiles((1.0, &a), (1.0, &b)), expected);
+        assert_eq!(rank_profiles((1.0, &b), (1.0, &a)), expected.reverse());
+    }
+}
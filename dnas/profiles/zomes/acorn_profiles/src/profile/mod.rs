@@ -1,12 +1,42 @@
 use dna_help::{
-    fetch_links, get_latest_for_entry, signal_peers, ActionType, EntryAndHash, WrappedAgentPubKey,
-    WrappedHeaderHash,
+    fetch_links, get_latest_for_entry, signal_peers,
+    validation::{
+        all_valid, entry_from_app_entry, must_not_be_longer_than, validate_delete_with,
+        validate_entry_with, validate_update_with,
+    },
+    AcornError, ActionType, EntryAndHash, WrappedAgentPubKey, WrappedHeaderHash,
 };
 use hdk3::prelude::*;
+use std::collections::BTreeMap;
 
-pub const AGENTS_PATH: &str = "agents";
+pub mod avatar;
+pub mod duplicates;
+pub mod presence;
+pub mod search;
+
+use avatar::{delete_avatars, must_be_own_avatar, FileManifest, AVATAR_TAG};
+use duplicates::{delete_links_to, fetch_profile_candidates, must_be_first_profile};
+use presence::{is_signed_by_agent, record_heartbeat, PresenceSignal};
+use search::{index_profile, search_fields_differ, unindex_agent};
 
-#[hdk_entry(id = "profile")]
+pub const AGENTS_PATH: &str = "agents";
+// every claimed handle has a path under this one,
+// which links to the profile of the agent who claimed it
+pub const HANDLES_PATH: &str = "handles";
+pub const HANDLE_TAG: &str = "handle";
+
+// Version 1 is the profile as it first was, and is what entries without a
+// `schema_version` are. Version 2 added bio, pronouns, timezone and fields.
+pub const PROFILE_SCHEMA_VERSION: u32 = 2;
+pub const MAX_NAME_LENGTH: usize = 100;
+pub const MAX_BIO_LENGTH: usize = 2000;
+pub const MAX_SHORT_FIELD_LENGTH: usize = 64;
+pub const MAX_FIELDS: usize = 32;
+pub const MAX_FIELD_VALUE_LENGTH: usize = 500;
+
+// Validating a new profile needs the author's whole source chain,
+// to check that they don't already have one.
+#[hdk_entry(id = "profile", required_validation_type = "full")]
 #[derive(Debug, Clone, PartialEq)]
 pub struct Profile {
     first_name: String,
@@ -15,6 +45,30 @@ pub struct Profile {
     status: Status,
     avatar_url: String,
     address: WrappedAgentPubKey,
+    // the fields below are left out when unset, so that profiles which
+    // don't use them are stored exactly as version 1 profiles were
+    #[serde(
+        default = "first_schema_version",
+        skip_serializing_if = "is_first_schema_version"
+    )]
+    schema_version: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    bio: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pronouns: Option<String>,
+    // an IANA time zone name, e.g. "Europe/Berlin"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    timezone: Option<String>,
+    // whatever else a team wants to keep on their profiles
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    fields: BTreeMap<String, String>,
+}
+
+fn first_schema_version() -> u32 {
+    1
+}
+fn is_first_schema_version(schema_version: &u32) -> bool {
+    *schema_version == 1
 }
 
 impl From<Profile> for AgentPubKey {
@@ -50,19 +104,37 @@ pub struct AgentsOutput(Vec<Profile>);
 #[derive(Serialize, Deserialize, SerializedBytes, Debug, Clone, PartialEq)]
 pub struct WhoAmIOutput(Option<WireEntry>);
 
-#[derive(SerializedBytes, Debug, Clone, PartialEq)]
+#[derive(Serialize, Deserialize, SerializedBytes, Debug, Clone, PartialEq)]
+pub struct FetchProfileOutput(Option<WireEntry>);
+
+#[derive(Serialize, Deserialize, SerializedBytes, Debug, Clone, PartialEq)]
+pub struct FetchProfilesInput(Vec<WrappedAgentPubKey>);
+
+#[derive(Serialize, Deserialize, SerializedBytes, Debug, Clone, PartialEq)]
+pub struct FetchProfilesOutput(Vec<Option<WireEntry>>);
+
+#[derive(Serialize, Deserialize, SerializedBytes, Debug, Clone, PartialEq)]
+pub struct FetchAgentByHandleInput(String);
+
+#[derive(Serialize, Deserialize, SerializedBytes, Debug, Clone, PartialEq)]
+pub struct FetchAgentByHandleOutput(Option<WireEntry>);
+
+// ordered from most to least present, see presence::presence_status
+#[derive(SerializedBytes, Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub enum Status {
     Online,
     Away,
     Offline,
 }
-impl From<String> for Status {
-    fn from(s: String) -> Self {
+// unknown values are refused, rather than taken to mean Offline
+impl TryFrom<String> for Status {
+    type Error = String;
+    fn try_from(s: String) -> Result<Self, Self::Error> {
         match s.as_str() {
-            "Online" => Self::Online,
-            "Away" => Self::Away,
-            // hack, should be explicit about Offline
-            _ => Self::Offline,
+            "Online" => Ok(Self::Online),
+            "Away" => Ok(Self::Away),
+            "Offline" => Ok(Self::Offline),
+            other => Err(format!("unknown status {:?}", other)),
         }
     }
 }
@@ -93,26 +165,278 @@ impl<'de> Deserialize<'de> for Status {
         D: serde::Deserializer<'de>,
     {
         let s = String::deserialize(deserializer)?;
-        match s.as_str() {
-            "Online" => Ok(Status::Online),
-            "Away" => Ok(Status::Away),
-            // hack, should be "Offline"
-            _ => Ok(Status::Offline),
+        Status::try_from(s).map_err(serde::de::Error::custom)
+    }
+}
+
+/*
+VALIDATION
+*/
+
+// only the agent a profile is about can create, update, or delete it
+fn must_be_profile_author(profile: &Profile, author: &AgentPubKey) -> ValidateCallbackResult {
+    if &profile.address.0 == author {
+        ValidateCallbackResult::Valid
+    } else {
+        ValidateCallbackResult::Invalid(
+            "only the same agent as the profile is about can create, update or delete their profile"
+                .into(),
+        )
+    }
+}
+
+// the sizes of everything in a profile are capped, and the fields
+// added in later versions can only be used by profiles of those versions
+pub fn validate_profile_fields(profile: &Profile) -> ValidateCallbackResult {
+    if profile.schema_version < 1 || profile.schema_version > PROFILE_SCHEMA_VERSION {
+        return ValidateCallbackResult::Invalid(format!(
+            "unknown profile schema_version {}",
+            profile.schema_version
+        ));
+    }
+    let has_later_fields = profile.bio.is_some()
+        || profile.pronouns.is_some()
+        || profile.timezone.is_some()
+        || !profile.fields.is_empty();
+    if profile.schema_version < 2 && has_later_fields {
+        return ValidateCallbackResult::Invalid(
+            "bio, pronouns, timezone and fields need schema_version 2".into(),
+        );
+    }
+    if profile.fields.len() > MAX_FIELDS {
+        return ValidateCallbackResult::Invalid(format!(
+            "a profile can have at most {} fields",
+            MAX_FIELDS
+        ));
+    }
+    let optional = |value: &Option<String>| value.clone().unwrap_or_default();
+    let mut results = vec![
+        must_not_be_longer_than(&profile.first_name, MAX_NAME_LENGTH, "first_name"),
+        must_not_be_longer_than(&profile.last_name, MAX_NAME_LENGTH, "last_name"),
+        must_not_be_longer_than(&profile.handle, MAX_SHORT_FIELD_LENGTH, "handle"),
+        must_not_be_longer_than(&optional(&profile.bio), MAX_BIO_LENGTH, "bio"),
+        must_not_be_longer_than(
+            &optional(&profile.pronouns),
+            MAX_SHORT_FIELD_LENGTH,
+            "pronouns",
+        ),
+        must_not_be_longer_than(
+            &optional(&profile.timezone),
+            MAX_SHORT_FIELD_LENGTH,
+            "timezone",
+        ),
+    ];
+    for (key, value) in profile.fields.iter() {
+        results.push(must_not_be_longer_than(
+            key,
+            MAX_SHORT_FIELD_LENGTH,
+            "a field name",
+        ));
+        results.push(must_not_be_longer_than(value, MAX_FIELD_VALUE_LENGTH, key));
+    }
+    all_valid(results)
+}
+
+#[hdk_extern]
+pub fn validate_create_entry_profile(
+    validate_data: ValidateData,
+) -> ExternResult<ValidateCallbackResult> {
+    let element = validate_data.element.clone();
+    let validation_package = validate_data.validation_package.clone();
+    Ok(all_valid(vec![
+        validate_entry_with(validate_data, |profile: Profile, author: &AgentPubKey| {
+            Ok(all_valid(vec![
+                must_be_profile_author(&profile, author),
+                validate_profile_fields(&profile),
+            ]))
+        })?,
+        must_be_first_profile(&element, validation_package),
+    ]))
+}
+
+#[hdk_extern]
+pub fn validate_update_entry_profile(
+    validate_data: ValidateData,
+) -> ExternResult<ValidateCallbackResult> {
+    validate_update_with(
+        validate_data,
+        |profile: Profile, original_profile: Profile, author: &AgentPubKey| {
+            // the profile can't be handed over to someone else either
+            Ok(all_valid(vec![
+                must_be_profile_author(&original_profile, author),
+                must_be_profile_author(&profile, author),
+                validate_profile_fields(&profile),
+            ]))
+        },
+    )
+}
+
+#[hdk_extern]
+pub fn validate_delete_entry_profile(
+    validate_data: ValidateData,
+) -> ExternResult<ValidateCallbackResult> {
+    validate_delete_with(validate_data, |profile: Profile, author: &AgentPubKey| {
+        Ok(must_be_profile_author(&profile, author))
+    })
+}
+
+// covers the links made in create_whoami: agents path -> profile,
+// agent -> profile, and handle path -> profile, the avatar path -> manifest
+// link made in upload_avatar, as well as the links between paths which Path::ensure makes
+#[hdk_extern]
+pub fn validate_create_link(
+    validate_data: ValidateCreateLinkData,
+) -> ExternResult<ValidateLinkCallbackResult> {
+    let author = validate_data.link_add.author.clone();
+    if entry_from_app_entry::<Path>(&validate_data.target).is_some() {
+        return Ok(ValidateLinkCallbackResult::Valid);
+    }
+    if validate_data.link_add.tag == LinkTag::new(AVATAR_TAG) {
+        return match entry_from_app_entry::<FileManifest>(&validate_data.target) {
+            Some(manifest) => must_be_own_avatar(&validate_data.base, &manifest, &author),
+            None => Ok(ValidateLinkCallbackResult::Invalid(
+                "avatar links must point at a file manifest".into(),
+            )),
+        };
+    }
+    let profile = match entry_from_app_entry::<Profile>(&validate_data.target) {
+        Some(profile) => profile,
+        None => {
+            return Ok(ValidateLinkCallbackResult::Invalid(
+                "links in this zome must point at a profile".into(),
+            ))
+        }
+    };
+    if profile.address.0 != author {
+        return Ok(ValidateLinkCallbackResult::Invalid(
+            "cannot link to other people's profile".into(),
+        ));
+    }
+    if validate_data.link_add.tag == LinkTag::new(HANDLE_TAG) {
+        return must_be_handle_path(&validate_data.base);
+    }
+    match validate_data.base {
+        Entry::Agent(agent_pubkey) if agent_pubkey != author => Ok(
+            ValidateLinkCallbackResult::Invalid("cannot link a profile from another agent".into()),
+        ),
+        _ => Ok(ValidateLinkCallbackResult::Valid),
+    }
+}
+
+// A handle link has to come from the path of a handle. It points at the
+// entry the profile was first created as, whose handle may have changed since,
+// so which handle it is can only be told by following that entry's updates.
+// That, and whether someone else holds the handle already, depend on what
+// each peer has seen, so they are left to claim_handle, which the zome
+// always uses.
+fn must_be_handle_path(base: &Entry) -> ExternResult<ValidateLinkCallbackResult> {
+    let is_handle_path = entry_from_app_entry::<Path>(base)
+        .map(|path| {
+            let components: &Vec<Component> = path.as_ref();
+            components.len() == 2 && components[0] == Component::from(HANDLES_PATH)
+        })
+        .unwrap_or(false);
+    if !is_handle_path {
+        return Ok(ValidateLinkCallbackResult::Invalid(
+            "a handle can only be claimed from the path of a handle".into(),
+        ));
+    }
+    Ok(ValidateLinkCallbackResult::Valid)
+}
+
+/*
+HANDLES
+*/
+
+// handles are unique regardless of case, so "@Alice" and "@alice" are the same.
+// The handle is one component, so that dots in it don't make more levels
+pub fn handle_path(handle: &str) -> Path {
+    Path::from(vec![
+        Component::from(HANDLES_PATH),
+        Component::from(handle.to_lowercase().as_str()),
+    ])
+}
+
+fn handle_taken(handle: &str) -> AcornError {
+    AcornError::ValidationFailed(format!("the handle {} is already taken", handle))
+}
+
+// The agent holding a handle, leaving out `except`. Handles are only kept
+// unique by claim_handle, so if two claims got through at the same moment,
+// both links stay. Every peer then settles on the same holder: the one whose
+// link has the lowest header address. Link times are left out, as the
+// author sets them.
+fn fetch_handle_holder(
+    handle: &str,
+    except: Option<&AgentPubKey>,
+) -> ExternResult<Option<WireEntry>> {
+    let mut links =
+        get_links(handle_path(handle).hash()?, Some(LinkTag::new(HANDLE_TAG)))?.into_inner();
+    links.sort_by_key(|link| link.create_link_hash.to_string());
+    for link in links {
+        if let Some(entry_and_hash) =
+            get_latest_for_entry::<Profile>(link.target, GetOptions::content())?
+        {
+            let wire_entry = WireEntry::from(entry_and_hash);
+            // the profile may have moved on to another handle
+            let still_holds = wire_entry.entry.handle.to_lowercase() == handle.to_lowercase();
+            if still_holds && Some(&wire_entry.entry.address.0) != except {
+                return Ok(Some(wire_entry));
+            }
+        }
+    }
+    Ok(None)
+}
+
+// Link the handle of `profile` to the entry the profile was first created
+// as, after making sure it's free, so that the UI gets a clear error instead
+// of a validation failure. Nothing else written in the same call is kept
+// when it fails.
+fn claim_handle(
+    profile: &Profile,
+    entry_hash: &EntryHash,
+    agent_pubkey: &AgentPubKey,
+) -> ExternResult<()> {
+    if fetch_handle_holder(&profile.handle, Some(agent_pubkey))?.is_some() {
+        return Err(handle_taken(&profile.handle).into());
+    }
+    let path = handle_path(&profile.handle);
+    path.ensure()?;
+    create_link(path.hash()?, entry_hash.clone(), LinkTag::new(HANDLE_TAG))?;
+    Ok(())
+}
+
+// remove my links from a handle I am moving away from
+fn release_handle(handle: &str, agent_pubkey: &AgentPubKey) -> ExternResult<()> {
+    for link in get_links(handle_path(handle).hash()?, Some(LinkTag::new(HANDLE_TAG)))?.into_inner()
+    {
+        if let Some(element) = get(link.target.clone(), GetOptions::content())? {
+            if element.header().author() == agent_pubkey {
+                delete_link(link.create_link_hash)?;
+            }
         }
     }
+    Ok(())
 }
 
-// #[hdk_extern]
-// fn validate(_: Entry) -> ExternResult<ValidateCallbackResult> {
-//     // HOLD
-//     // have to hold on this until we get more than entry in the validation callback
-//     // which will come soon, according to David M.
-//     let _ = debug!(("in validation callback");
-//     Ok(ValidateCallbackResult::Valid)
-// }
+#[hdk_extern]
+pub fn fetch_agent_by_handle(
+    input: FetchAgentByHandleInput,
+) -> ExternResult<FetchAgentByHandleOutput> {
+    let holder = fetch_handle_holder(&input.0, None)?;
+    Ok(FetchAgentByHandleOutput(holder))
+}
 
 #[hdk_extern]
 pub fn create_whoami(entry: Profile) -> ExternResult<WireEntry> {
+    let agent_pubkey = agent_info()?.agent_initial_pubkey;
+    // an agent only gets one profile, so this is an edit of the one they have
+    if let Some(existing) = inner_fetch_profile(agent_pubkey.clone())? {
+        return update_whoami(WireEntry {
+            entry,
+            address: existing.address,
+        });
+    }
     // commit this new profile
     let header_hash = create_entry(&entry)?;
 
@@ -123,9 +447,13 @@ pub fn create_whoami(entry: Profile) -> ExternResult<WireEntry> {
     create_link(agents_path_address, entry_hash.clone(), ())?;
 
     // list me so I can specifically and quickly look up my profile
-    let agent_pubkey = agent_info()?.agent_initial_pubkey;
-    let agent_entry_hash = EntryHash::from(agent_pubkey);
-    create_link(agent_entry_hash, entry_hash, ())?;
+    let agent_entry_hash = EntryHash::from(agent_pubkey.clone());
+    create_link(agent_entry_hash, entry_hash.clone(), ())?;
+
+    // and so that anyone can look me up by my handle
+    claim_handle(&entry, &entry_hash, &agent_pubkey)?;
+    // or find me by the start of my handle or name
+    index_profile(&entry, &entry_hash)?;
 
     let wire_entry = WireEntry {
         entry,
@@ -147,9 +475,41 @@ fn agent_signal_entry_type() -> String {
     "agent".to_string()
 }
 
+// the entry a profile was first created as, which every link to it points at
+fn original_entry_hash(address: &WrappedHeaderHash) -> ExternResult<EntryHash> {
+    let element = match get(address.0.clone(), GetOptions::content())? {
+        Some(element) => element,
+        None => return Err(AcornError::NotFound("no profile exists".into()).into()),
+    };
+    match element.header() {
+        Header::Update(update) => Ok(update.original_entry_address.clone()),
+        Header::Create(create) => Ok(create.entry_hash.clone()),
+        _ => Err(AcornError::NotFound("no profile exists".into()).into()),
+    }
+}
+
 #[hdk_extern]
 pub fn update_whoami(update: WireEntry) -> ExternResult<WireEntry> {
+    let agent_pubkey = agent_info()?.agent_initial_pubkey;
+    let previous = whoami(())?.0.map(|wire_entry| wire_entry.entry);
     update_entry(update.address.0.clone(), &update.entry)?;
+    // The handle and search links point at the entry the profile was first
+    // created as, and are read through its updates, so they are only made
+    // again when the handle or a name changes.
+    let reindex = match previous {
+        Some(previous) if search_fields_differ(&previous, &update.entry) => {
+            release_handle(&previous.handle, &agent_pubkey)?;
+            unindex_agent(&previous, &agent_pubkey)?;
+            true
+        }
+        Some(_) => false,
+        None => true,
+    };
+    if reindex {
+        let entry_hash = original_entry_hash(&update.address)?;
+        claim_handle(&update.entry, &entry_hash, &agent_pubkey)?;
+        index_profile(&update.entry, &entry_hash)?;
+    }
     // // send update to peers
     // we don't want to cause real failure for inability to send to peers
     let signal = AgentSignal {
@@ -161,29 +521,105 @@ pub fn update_whoami(update: WireEntry) -> ExternResult<WireEntry> {
     Ok(update)
 }
 
+// delete a profile, along with the updates made to it
+fn delete_profile_headers(address: HeaderHash) -> ExternResult<()> {
+    if let Some(Details::Element(details)) = get_details(address.clone(), GetOptions::latest())? {
+        for update in details.updates {
+            delete_entry(update.header_address().clone())?;
+        }
+    }
+    delete_entry(address)?;
+    Ok(())
+}
+
+// Forget me: delete my profile and every link to it, so that I'm no longer
+// listed, found by my handle or name, or shown with an avatar.
+#[hdk_extern]
+pub fn delete_whoami(_: ()) -> ExternResult<WrappedHeaderHash> {
+    let agent_pubkey = agent_info()?.agent_initial_pubkey;
+    let candidates = fetch_profile_candidates(agent_pubkey.clone())?;
+    let address = match candidates.first() {
+        Some(candidate) => candidate.wire_entry.address.clone(),
+        None => return Err(AcornError::NotFound("there is no profile to delete".into()).into()),
+    };
+    // duplicates from before they were refused go too
+    for candidate in candidates {
+        let target = candidate.linked_entry_hash;
+        delete_links_to(EntryHash::from(agent_pubkey.clone()), None, &target)?;
+        delete_links_to(Path::from(AGENTS_PATH).hash()?, None, &target)?;
+        release_handle(&candidate.wire_entry.entry.handle, &agent_pubkey)?;
+        unindex_agent(&candidate.wire_entry.entry, &agent_pubkey)?;
+        delete_profile_headers(candidate.wire_entry.address.0)?;
+    }
+    delete_avatars(&agent_pubkey)?;
+    // we don't want to cause real failure for inability to send to peers
+    let signal = AgentSignal {
+        entry_type: agent_signal_entry_type(),
+        action: ActionType::Delete,
+        data: SignalData::Delete(address.clone()),
+    };
+    let _ = send_agent_signal(signal);
+    Ok(address)
+}
+
 #[hdk_extern]
 pub fn whoami(_: ()) -> ExternResult<WhoAmIOutput> {
     let agent_pubkey = agent_info()?.agent_initial_pubkey;
-    let agent_entry_hash = EntryHash::from(agent_pubkey);
-
-    let all_profiles = get_links(agent_entry_hash, None)?.into_inner();
-    let maybe_profile_link = all_profiles.last();
-    // // do it this way so that we always keep the original profile entry address
-    // // from the UI perspective
-    match maybe_profile_link {
-        Some(profile_link) => match get_latest_for_entry::<Profile>(profile_link.target.clone(), GetOptions::content())? {
-            Some(entry_and_hash) => Ok(WhoAmIOutput(Some(WireEntry::from(entry_and_hash)))),
-            None => Ok(WhoAmIOutput(None)),
-        },
-        None => Ok(WhoAmIOutput(None)),
+    Ok(WhoAmIOutput(inner_fetch_profile(agent_pubkey)?))
+}
+
+// the profile of any agent, found through the agent -> profile link made in create_whoami.
+// If they have more than one from before that was refused, it's the first.
+fn inner_fetch_profile(agent_pubkey: AgentPubKey) -> ExternResult<Option<WireEntry>> {
+    Ok(fetch_profile_candidates(agent_pubkey)?
+        .into_iter()
+        .next()
+        .map(|candidate| candidate.wire_entry))
+}
+
+#[hdk_extern]
+pub fn fetch_profile(agent_address: WrappedAgentPubKey) -> ExternResult<FetchProfileOutput> {
+    Ok(FetchProfileOutput(inner_fetch_profile(agent_address.0)?))
+}
+
+// in the same order as the agents asked for, with null for those without a profile
+#[hdk_extern]
+pub fn fetch_profiles(input: FetchProfilesInput) -> ExternResult<FetchProfilesOutput> {
+    let mut profiles = Vec::new();
+    for agent_address in input.0 {
+        profiles.push(inner_fetch_profile(agent_address.0)?);
     }
+    Ok(FetchProfilesOutput(profiles))
 }
 
 #[hdk_extern]
 pub fn fetch_agents(_: ()) -> ExternResult<AgentsOutput> {
     let path_hash = Path::from(AGENTS_PATH).hash()?;
-    let entries = fetch_links::<Profile, Profile>(path_hash, GetOptions::content())?;
-    Ok(AgentsOutput(entries))
+    // latest, so that deleted profiles are left out as soon as they are deleted
+    let entries = fetch_links::<Profile, Profile>(path_hash, GetOptions::latest())?;
+    // agents with more than one profile are listed once, with the one whoami gives them
+    let mut profiles: Vec<Profile> = Vec::new();
+    for profile in entries.iter() {
+        if profiles
+            .iter()
+            .any(|listed| listed.address == profile.address)
+        {
+            continue;
+        }
+        let duplicated = entries
+            .iter()
+            .filter(|other| other.address == profile.address)
+            .count()
+            > 1;
+        if duplicated {
+            if let Some(wire_entry) = inner_fetch_profile(profile.address.0.clone())? {
+                profiles.push(wire_entry.entry);
+            }
+        } else {
+            profiles.push(profile.clone());
+        }
+    }
+    Ok(AgentsOutput(profiles))
 }
 
 #[hdk_extern]
@@ -230,83 +666,153 @@ pub struct AgentSignal {
     pub data: SignalData,
 }
 
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, SerializedBytes)]
+// untagged because the useful tagging is done internally on the *Signal objects
+#[serde(untagged)]
+pub enum SignalType {
+    Agent(AgentSignal),
+    Presence(PresenceSignal),
+}
+
 // receiver (and forward to UI)
 #[hdk_extern]
 pub fn recv_remote_signal(sb: SerializedBytes) -> ExternResult<()> {
-    let signal: AgentSignal = AgentSignal::try_from(sb)?;
+    let signal: SignalType = SignalType::try_from(sb)?;
+    if let SignalType::Presence(presence_signal) = &signal {
+        // anyone could send a heartbeat naming someone else
+        if !is_signed_by_agent(presence_signal)? {
+            return Ok(());
+        }
+        // still forward it if it couldn't be written down
+        let _ = record_heartbeat(&presence_signal.data);
+    }
     Ok(emit_signal(&signal)?)
 }
 
-// pub fn profile_def() -> ValidatingEntryType {
-//     entry!(
-//         name: "profile",
-//         description: "this is an entry representing some profile info for an agent",
-//         sharing: Sharing::Public,
-//         validation_package: || {
-//             hdk::ValidationPackageDefinition::Entry
-//         },
-//         validation: | validation_data: hdk::EntryValidationData<Profile>| {
-//             match validation_data{
-//                 hdk::EntryValidationData::Create{entry,validation_data}=>{
-//                     let agent_address = &validation_data.sources()[0];
-//                     if entry.address!=agent_address.to_string() {
-//                         Err("only the same agent as the profile is about can create their profile".into())
-//                     }else {Ok(())}
-//                 },
-//                 hdk::EntryValidationData::Modify{
-//                     new_entry,
-//                     old_entry,validation_data,..}=>{
-//                     let agent_address = &validation_data.sources()[0];
-//                     if new_entry.address!=agent_address.to_string()&& old_entry.address!=agent_address.to_string(){
-//                         Err("only the same agent as the profile is about can modify their profile".into())
-//                     }else {Ok(())}
-//                 },
-//                 hdk::EntryValidationData::Delete{old_entry,validation_data,..}=>{
-//                     let agent_address = &validation_data.sources()[0];
-//                     if old_entry.address!=agent_address.to_string() {
-//                         Err("only the same agent as the profile is about can delete their profile".into())
-//                     }else {Ok(())}
-//                 }
-//             }
-//         },
-//         links: [
-//             from!(
-//                 "%agent_id",
-//                 link_type: "agent->profile",
-//                 validation_package: || {
-//                     hdk::ValidationPackageDefinition::Entry
-//                 },
-//                validation: |link_validation_data: hdk::LinkValidationData| {
-//                     let validation_data =
-//                         match link_validation_data {
-//                             hdk::LinkValidationData::LinkAdd {
-//                                 validation_data,..
-//                             } => validation_data,
-//                             hdk::LinkValidationData::LinkRemove {
-//                                 validation_data,..
-//                             } =>validation_data,
-//                         };
-//                     let agent_address=&validation_data.sources()[0];
-//                     if let Some(vector)= validation_data.package.source_chain_entries{
-//                         if let App (_,entry)=&vector[0]{
-//                         if let Ok(profile)=serde_json::from_str::<Profile>(&Into::<String>::into(entry)) {
-//                             if profile.address==agent_address.to_string(){
-//                             Ok(())
-
-//                             }else {
-//                         Err("Cannot edit other people's Profile1".to_string())}
-//                         }else {
-//                         Err("Cannot edit other people's Profile2".to_string())}
-//                     }
-//                     else{
-//                         Err("Cannot edit other people's Profile3".to_string())
-//                     }
-
-//                     } else{
-//                         Ok(())
-//                     }
-//                     }
-//             )
-//         ]
-//     )
-// }
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn profile() -> Profile {
+        Profile {
+            first_name: "c".into(),
+            last_name: "t".into(),
+            handle: "ct".into(),
+            status: Status::Online,
+            avatar_url: "".into(),
+            address: WrappedAgentPubKey(AgentPubKey::from_raw_36(vec![1; 36])),
+            schema_version: 1,
+            bio: None,
+            pronouns: None,
+            timezone: None,
+            fields: BTreeMap::new(),
+        }
+    }
+
+    #[test]
+    fn only_handles_and_names_are_searched() {
+        let away = Profile {
+            status: Status::Away,
+            bio: Some("hi".into()),
+            ..profile()
+        };
+        assert!(!search_fields_differ(&profile(), &away));
+        let renamed = Profile {
+            last_name: "u".into(),
+            ..profile()
+        };
+        assert!(search_fields_differ(&profile(), &renamed));
+        let rehandled = Profile {
+            handle: "cu".into(),
+            ..profile()
+        };
+        assert!(search_fields_differ(&profile(), &rehandled));
+    }
+
+    #[test]
+    fn statuses_must_be_known() {
+        assert_eq!(Status::try_from("Away".to_string()), Ok(Status::Away));
+        assert!(Status::try_from("Ofline".to_string()).is_err());
+        // and the same goes for deserializing them
+        #[derive(Serialize, Deserialize, SerializedBytes, Debug)]
+        struct Text(String);
+        let sb = SerializedBytes::try_from(Text("Ofline".into())).unwrap();
+        assert!(Status::try_from(sb).is_err());
+    }
+
+    #[test]
+    fn first_version_profiles_stay_as_they_were() {
+        let sb = SerializedBytes::try_from(profile()).unwrap();
+        #[derive(Serialize, Deserialize, SerializedBytes, Debug)]
+        struct FirstVersionProfile {
+            first_name: String,
+            last_name: String,
+            handle: String,
+            status: Status,
+            avatar_url: String,
+            address: WrappedAgentPubKey,
+        }
+        // nothing more is written for them than before
+        let first_version = FirstVersionProfile::try_from(sb.clone()).unwrap();
+        assert_eq!(SerializedBytes::try_from(first_version).unwrap(), sb);
+        // and they read back as version 1
+        assert_eq!(Profile::try_from(sb).unwrap(), profile());
+    }
+
+    #[test]
+    fn later_fields_need_a_later_version() {
+        let bio = Profile {
+            bio: Some("hi".into()),
+            ..profile()
+        };
+        assert_ne!(validate_profile_fields(&bio), ValidateCallbackResult::Valid);
+        assert_eq!(
+            validate_profile_fields(&Profile {
+                schema_version: 2,
+                ..bio
+            }),
+            ValidateCallbackResult::Valid
+        );
+        assert_ne!(
+            validate_profile_fields(&Profile {
+                schema_version: PROFILE_SCHEMA_VERSION + 1,
+                ..profile()
+            }),
+            ValidateCallbackResult::Valid
+        );
+    }
+
+    #[test]
+    fn profile_sizes_are_capped() {
+        let version_2 = Profile {
+            schema_version: 2,
+            ..profile()
+        };
+        assert_ne!(
+            validate_profile_fields(&Profile {
+                bio: Some("a".repeat(MAX_BIO_LENGTH + 1)),
+                ..version_2.clone()
+            }),
+            ValidateCallbackResult::Valid
+        );
+        let mut fields = BTreeMap::new();
+        fields.insert("team".to_string(), "a".repeat(MAX_FIELD_VALUE_LENGTH + 1));
+        assert_ne!(
+            validate_profile_fields(&Profile {
+                fields: fields.clone(),
+                ..version_2.clone()
+            }),
+            ValidateCallbackResult::Valid
+        );
+        let too_many: BTreeMap<String, String> = (0..=MAX_FIELDS)
+            .map(|i| (i.to_string(), "a".into()))
+            .collect();
+        assert_ne!(
+            validate_profile_fields(&Profile {
+                fields: too_many,
+                ..version_2
+            }),
+            ValidateCallbackResult::Valid
+        );
+    }
+}
@@ -0,0 +1,251 @@
+use super::{fetch_agents, get_peers, whoami, Profile, Status};
+use dna_help::{signal_peers, validation::entry_from_element, ActionType, WrappedAgentPubKey};
+use hdk3::prelude::*;
+use std::collections::HashMap;
+
+/*
+  PRESENCE
+  The UI calls `heartbeat` every minute or so while it's open. That sends a
+  remote signal to peers, and writes nothing itself. Peers keep the time they
+  last heard from each agent as a private entry on their own source chain,
+  which is never published, and work out from it whether that agent is
+  Online, Away or Offline. Profiles are left alone, so their status is only
+  what the agent chose, e.g. to appear Away.
+  There is one LastSeen entry per agent, which is updated at most every
+  LAST_SEEN_PRECISION seconds, so that heartbeats don't each add a new entry.
+  Remote signals don't say who sent them, so a heartbeat carries the sender's
+  signature of its presence, and ones that aren't signed by the agent they
+  are about are dropped.
+*/
+
+// a peer's last seen time is only written down again
+// once it is at least this many seconds old
+const LAST_SEEN_PRECISION: f64 = 30.0;
+
+// The timeouts, in seconds, can be set in the DNA properties,
+// e.g. `{ "away_after": 300, "offline_after": 900 }`
+#[derive(Serialize, Deserialize, Debug, SerializedBytes, Clone, PartialEq)]
+pub struct PresenceProperties {
+    #[serde(default = "default_away_after")]
+    pub away_after: f64,
+    #[serde(default = "default_offline_after")]
+    pub offline_after: f64,
+}
+
+fn default_away_after() -> f64 {
+    300.0
+}
+fn default_offline_after() -> f64 {
+    900.0
+}
+
+impl Default for PresenceProperties {
+    fn default() -> Self {
+        Self {
+            away_after: default_away_after(),
+            offline_after: default_offline_after(),
+        }
+    }
+}
+
+fn presence_properties() -> ExternResult<PresenceProperties> {
+    Ok(PresenceProperties::try_from(zome_info()?.properties).unwrap_or_default())
+}
+
+// when we last heard a heartbeat from an agent. private, so it stays on our own chain
+#[hdk_entry(id = "last_seen", visibility = "private")]
+#[derive(Debug, Clone, PartialEq)]
+pub struct LastSeen {
+    pub agent_address: WrappedAgentPubKey,
+    pub unix_timestamp: f64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, SerializedBytes)]
+pub struct Presence {
+    pub agent_address: WrappedAgentPubKey,
+    // None if we haven't heard from them
+    pub last_seen: Option<f64>,
+    pub status: Status,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, SerializedBytes)]
+pub struct VecPresence(Vec<Presence>);
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, SerializedBytes)]
+pub struct PresenceSignal {
+    pub entry_type: String,
+    pub action: ActionType,
+    pub data: Presence,
+    // by data.agent_address, of data
+    pub signature: Signature,
+}
+
+// The status to show for an agent: the one they chose, unless they've been
+// quiet for long enough to be Away or Offline. Someone who chose
+// to appear Away or Offline stays that way while they are active.
+pub fn presence_status(
+    chosen: &Status,
+    seconds_since_seen: Option<f64>,
+    properties: &PresenceProperties,
+) -> Status {
+    let by_time = match seconds_since_seen {
+        Some(seconds) if seconds < properties.away_after => Status::Online,
+        Some(seconds) if seconds < properties.offline_after => Status::Away,
+        _ => Status::Offline,
+    };
+    std::cmp::max(chosen.clone(), by_time)
+}
+
+// the entry type of LastSeen, so that only those are queried for
+fn last_seen_entry_type() -> ExternResult<EntryType> {
+    Ok(EntryType::App(AppEntryType::new(
+        entry_def_index!(LastSeen)?,
+        zome_info()?.zome_id,
+        EntryVisibility::Private,
+    )))
+}
+
+// the latest time we heard from each agent, with the
+// header of the LastSeen entry it was written down in
+fn fetch_last_seen() -> ExternResult<HashMap<AgentPubKey, (HeaderHash, f64)>> {
+    let filter = QueryFilter::new()
+        .entry_type(last_seen_entry_type()?)
+        .include_entries(true);
+    let mut last_seen: HashMap<AgentPubKey, (HeaderHash, f64)> = HashMap::new();
+    for element in query(filter)?.0 {
+        let record = match entry_from_element::<LastSeen>(&element)? {
+            Some(record) => record,
+            None => continue,
+        };
+        let header_address = element.header_address().clone();
+        match last_seen.get(&record.agent_address.0) {
+            Some((_, time)) if *time >= record.unix_timestamp => (),
+            _ => {
+                last_seen.insert(
+                    record.agent_address.0,
+                    (header_address, record.unix_timestamp),
+                );
+            }
+        }
+    }
+    Ok(last_seen)
+}
+
+// whether a heartbeat was sent by the agent it is about
+pub fn is_signed_by_agent(signal: &PresenceSignal) -> ExternResult<bool> {
+    Ok(verify_signature(
+        signal.data.agent_address.0.clone(),
+        signal.signature.clone(),
+        signal.data.clone(),
+    )?)
+}
+
+// Write down when we heard from the agent, which is when they signed the
+// heartbeat, so that one sent again later doesn't count as more recent.
+pub fn record_heartbeat(presence: &Presence) -> ExternResult<()> {
+    let now = sys_time()?.as_secs_f64();
+    let heard_at = match presence.last_seen {
+        Some(last_seen) => last_seen.min(now),
+        None => return Ok(()),
+    };
+    let last_seen = LastSeen {
+        agent_address: presence.agent_address.clone(),
+        unix_timestamp: heard_at,
+    };
+    match fetch_last_seen()?.remove(&presence.agent_address.0) {
+        None => {
+            create_entry(&last_seen)?;
+        }
+        Some((header_address, previous)) if heard_at - previous >= LAST_SEEN_PRECISION => {
+            update_entry(header_address, &last_seen)?;
+        }
+        Some(_) => (),
+    }
+    Ok(())
+}
+
+// let peers know we are here, without writing anything
+#[hdk_extern]
+pub fn heartbeat(_: ()) -> ExternResult<()> {
+    let agent_address = WrappedAgentPubKey(agent_info()?.agent_initial_pubkey);
+    // before there is a profile, we have nothing to show peers
+    let status = match whoami(())?.0 {
+        Some(wire_entry) => wire_entry.entry.status,
+        None => return Ok(()),
+    };
+    let presence = Presence {
+        agent_address: agent_address.clone(),
+        last_seen: Some(sys_time()?.as_secs_f64()),
+        status,
+    };
+    let signal = PresenceSignal {
+        entry_type: "presence".to_string(),
+        action: ActionType::Update,
+        signature: sign(agent_address.0, presence.clone())?,
+        data: presence,
+    };
+    signal_peers(&signal, get_peers)
+}
+
+#[hdk_extern]
+pub fn fetch_presence(_: ()) -> ExternResult<VecPresence> {
+    let properties = presence_properties()?;
+    let me = agent_info()?.agent_initial_pubkey;
+    let now = sys_time()?.as_secs_f64();
+    let last_seen = fetch_last_seen()?;
+    Ok(VecPresence(
+        fetch_agents(())?
+            .0
+            .into_iter()
+            .map(|profile: Profile| {
+                let seen = if profile.address.0 == me {
+                    Some(now)
+                } else {
+                    last_seen.get(&profile.address.0).map(|(_, seen)| *seen)
+                };
+                Presence {
+                    status: presence_status(
+                        &profile.status,
+                        seen.map(|seen| now - seen),
+                        &properties,
+                    ),
+                    agent_address: profile.address,
+                    last_seen: seen,
+                }
+            })
+            .collect(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quiet_agents_become_away_then_offline() {
+        let properties = PresenceProperties::default();
+        let status = |seconds| presence_status(&Status::Online, seconds, &properties);
+        assert_eq!(status(Some(0.0)), Status::Online);
+        assert_eq!(status(Some(properties.away_after)), Status::Away);
+        assert_eq!(status(Some(properties.offline_after)), Status::Offline);
+        assert_eq!(status(None), Status::Offline);
+    }
+
+    #[test]
+    fn chosen_status_is_kept_while_active() {
+        let properties = PresenceProperties::default();
+        assert_eq!(
+            presence_status(&Status::Away, Some(0.0), &properties),
+            Status::Away
+        );
+        assert_eq!(
+            presence_status(&Status::Offline, Some(0.0), &properties),
+            Status::Offline
+        );
+        // but being quiet still wins over choosing Away
+        assert_eq!(
+            presence_status(&Status::Away, None, &properties),
+            Status::Offline
+        );
+    }
+}
@@ -0,0 +1,162 @@
+use super::{AgentsOutput, Profile};
+use dna_help::get_latest_for_entry;
+use hdk3::prelude::*;
+use std::collections::HashSet;
+
+/*
+  SEARCH
+  Every profile is linked from a bucket for each of the first few letters of
+  its handle, first name and last name, e.g. `profile_search.handle.a`,
+  `profile_search.handle.al` and `profile_search.handle.ali` for "alice".
+  A search only reads the one bucket for the start of the query.
+*/
+
+pub const SEARCH_PATH: &str = "profile_search";
+pub const SEARCH_TAG: &str = "profile_search";
+// longer queries are narrowed down from the bucket of their first letters
+const MAX_PREFIX_LENGTH: usize = 3;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, SerializedBytes)]
+pub struct SearchProfilesInput {
+    pub query: String,
+    pub limit: u32,
+}
+
+// the fields a profile can be found by, in the order their matches are listed
+const SEARCHABLE_FIELDS: [&str; 3] = ["handle", "first_name", "last_name"];
+
+fn field_value<'a>(profile: &'a Profile, field: &str) -> &'a str {
+    match field {
+        "handle" => &profile.handle,
+        "first_name" => &profile.first_name,
+        _ => &profile.last_name,
+    }
+}
+
+pub fn normalize(text: &str) -> String {
+    text.trim().to_lowercase()
+}
+
+// the prefixes of a value which it is linked from, shortest first
+pub fn prefixes(value: &str) -> Vec<String> {
+    let normalized: Vec<char> = normalize(value).chars().collect();
+    (1..=normalized.len().min(MAX_PREFIX_LENGTH))
+        .map(|length| normalized[..length].iter().collect())
+        .collect()
+}
+
+fn bucket_path(field: &str, prefix: &str) -> Path {
+    Path::from(vec![
+        Component::from(SEARCH_PATH),
+        Component::from(field),
+        Component::from(prefix),
+    ])
+}
+
+// every bucket a profile should be linked from
+fn buckets(profile: &Profile) -> Vec<Path> {
+    SEARCHABLE_FIELDS
+        .iter()
+        .flat_map(|field| {
+            prefixes(field_value(profile, field))
+                .into_iter()
+                .map(move |prefix| bucket_path(field, &prefix))
+        })
+        .collect()
+}
+
+// Link the entry the profile was first created as from the buckets of
+// `profile`, its latest version, which is found from it through its updates.
+pub fn index_profile(profile: &Profile, entry_hash: &EntryHash) -> ExternResult<()> {
+    for path in buckets(profile) {
+        path.ensure()?;
+        create_link(path.hash()?, entry_hash.clone(), LinkTag::new(SEARCH_TAG))?;
+    }
+    Ok(())
+}
+
+// whether a profile would be linked from other buckets than before
+pub fn search_fields_differ(a: &Profile, b: &Profile) -> bool {
+    SEARCHABLE_FIELDS
+        .iter()
+        .any(|field| field_value(a, field) != field_value(b, field))
+}
+
+// Remove the links the agent made from the buckets of their profile. These
+// can point at an older version of it than `profile`, as update_whoami only
+// makes them again when the handle or a name changes.
+pub fn unindex_agent(profile: &Profile, agent_pubkey: &AgentPubKey) -> ExternResult<()> {
+    for path in buckets(profile) {
+        for link in get_links(path.hash()?, Some(LinkTag::new(SEARCH_TAG)))?.into_inner() {
+            if let Some(element) = get(link.target.clone(), GetOptions::content())? {
+                if element.header().author() == agent_pubkey {
+                    delete_link(link.create_link_hash)?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+// remove the links to an old version of a profile
+pub fn unindex_profile(profile: &Profile) -> ExternResult<()> {
+    let entry_hash = hash_entry(profile)?;
+    for path in buckets(profile) {
+        for link in get_links(path.hash()?, Some(LinkTag::new(SEARCH_TAG)))?.into_inner() {
+            if link.target == entry_hash {
+                delete_link(link.create_link_hash)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+// Profiles whose handle, first name or last name starts with the query,
+// ignoring case. Matches on handle come first, then first name, then last name.
+#[hdk_extern]
+pub fn search_profiles(input: SearchProfilesInput) -> ExternResult<AgentsOutput> {
+    let query = normalize(&input.query);
+    let bucket_prefix = match prefixes(&query).pop() {
+        Some(bucket_prefix) => bucket_prefix,
+        None => return Ok(AgentsOutput(Vec::new())),
+    };
+    let limit = input.limit as usize;
+    let mut seen = HashSet::new();
+    let mut profiles = Vec::new();
+    for field in SEARCHABLE_FIELDS.iter() {
+        let path_hash = bucket_path(field, &bucket_prefix).hash()?;
+        for link in get_links(path_hash, Some(LinkTag::new(SEARCH_TAG)))?.into_inner() {
+            if profiles.len() >= limit {
+                return Ok(AgentsOutput(profiles));
+            }
+            // links can be left behind by old versions, so check the latest one
+            let latest = get_latest_for_entry::<Profile>(link.target, GetOptions::latest())?;
+            let profile = match latest {
+                Some((profile, _, _)) => profile,
+                None => continue,
+            };
+            let matches = normalize(field_value(&profile, field)).starts_with(&query);
+            if matches && seen.insert(profile.address.0.clone()) {
+                profiles.push(profile);
+            }
+        }
+    }
+    Ok(AgentsOutput(profiles))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prefixes_are_lowercased_and_capped() {
+        assert_eq!(prefixes(" Alice"), vec!["a", "al", "ali"]);
+        assert_eq!(prefixes("Al"), vec!["a", "al"]);
+        assert_eq!(prefixes(""), Vec::<String>::new());
+    }
+
+    #[test]
+    fn prefixes_count_letters_not_bytes() {
+        assert_eq!(prefixes("Éloïse"), vec!["é", "él", "élo"]);
+    }
+}
@@ -1,19 +1,37 @@
 use std::time::Duration;
 
-use dna_help::{create_receive_signal_cap_grant, fetch_links, signal_peers, WrappedAgentPubKey};
+use dna_help::{
+    changes::{self, Change},
+    create_receive_signal_cap_grant, fetch_links,
+    paging::get_link_time,
+    signal_peers,
+    validation::entry_from_element,
+    ActionType, WrappedAgentPubKey,
+};
 use hdk3::prelude::*;
 
 mod project;
 
 use project::{
-    edge::{Edge, EdgeSignal},
-    entry_point::{EntryPoint, EntryPointSignal},
-    goal::{ArchiveGoalFullySignal, Goal, GoalSignal, GoalWithEdgeSignal},
-    goal_comment::{GoalComment, GoalCommentSignal},
-    goal_member::{GoalMember, GoalMemberSignal},
-    goal_vote::{GoalVote, GoalVoteSignal},
-    member::{Member, MemberSignal, MEMBER_PATH},
-    project_meta::{ProjectMeta, ProjectMetaSignal},
+    edge::{inner_fetch_edge_changes_since, Edge, EdgeSignal, ReparentGoalSignal},
+    entry_point::{inner_fetch_entry_point_changes_since, EntryPoint, EntryPointSignal},
+    goal::{
+        inner_fetch_goal_changes_since, ArchiveGoalFullySignal, Goal, GoalSignal,
+        GoalWithEdgeSignal,
+    },
+    goal_comment::{inner_fetch_goal_comment_changes_since, GoalComment, GoalCommentSignal},
+    goal_member::{inner_fetch_goal_member_changes_since, GoalMember, GoalMemberSignal},
+    goal_vote::{inner_fetch_goal_vote_changes_since, GoalVote, GoalVoteSignal},
+    invitation::{
+        create_approve_redemption_cap_grant, inner_redeem_invitation, validate_redemption_link,
+        Invitation, InvitationRedemption, REDEMPTION_TAG,
+    },
+    member::{my_invitation_code, Member, MemberSignal, MEMBER_PATH},
+    project_meta::{inner_fetch_project_meta_changes_since, ProjectMeta, ProjectMetaSignal},
+    role::{
+        fetch_role_changes_since, validate_role_link, RoleAssignment, RoleSignal, ROLE_LINK_TAG,
+    },
+    trash::RestoreGoalFullySignal,
 };
 
 #[hdk_extern]
@@ -23,6 +41,8 @@ fn init(_: ()) -> ExternResult<InitCallbackResult> {
     // authorize receive_signal
     // not the issue, takes about 2 ms
     create_receive_signal_cap_grant()?;
+    // so that agents with the code of an invitation I made can redeem it
+    create_approve_redemption_cap_grant()?;
 
     Path::from(MEMBER_PATH).ensure()?;
 
@@ -36,6 +56,14 @@ fn init(_: ()) -> ExternResult<InitCallbackResult> {
     let member_entry_hash = hash_entry(&member)?;
     create_link(member_path_address, member_entry_hash, ())?;
 
+    // if we were invited, use up the invitation, which is
+    // also what gives us the role that came with it
+    if let Some(invitation_code) = my_invitation_code()? {
+        if let Err(e) = inner_redeem_invitation(invitation_code) {
+            return Ok(InitCallbackResult::Fail(format!("{:?}", e)));
+        }
+    }
+
     let end_init_time: Duration = sys_time()?;
     debug!("end of init time {:?}", end_init_time.clone());
     debug!(
@@ -53,10 +81,28 @@ entry_defs!(
     GoalComment::entry_def(),
     GoalMember::entry_def(),
     GoalVote::entry_def(),
+    Invitation::entry_def(),
+    InvitationRedemption::entry_def(),
     Member::entry_def(),
-    ProjectMeta::entry_def()
+    ProjectMeta::entry_def(),
+    RoleAssignment::entry_def()
 );
 
+// Only links which give someone a role are checked, the rest are valid.
+#[hdk_extern]
+fn validate_create_link(
+    validate_data: ValidateCreateLinkData,
+) -> ExternResult<ValidateLinkCallbackResult> {
+    let tag = validate_data.link_add.tag.clone();
+    if tag == LinkTag::new(ROLE_LINK_TAG) {
+        validate_role_link(validate_data)
+    } else if tag == LinkTag::new(REDEMPTION_TAG) {
+        validate_redemption_link(validate_data)
+    } else {
+        Ok(ValidateLinkCallbackResult::Valid)
+    }
+}
+
 /*
 SIGNALS
 */
@@ -77,11 +123,20 @@ pub enum SignalType {
     // both the archived goal, and everything connected to it that
     // was archived at the same time
     ArchiveGoalFully(ArchiveGoalFullySignal),
+    // custom signal type for reparent_goal
+    // this is because the UI should never see the goal
+    // with both its old and new parent, or with neither
+    ReparentGoal(ReparentGoalSignal),
+    // custom signal type for restore_goal_fully
+    // this is the counterpart of ArchiveGoalFully, so the UI
+    // gets the goal back along with everything connected to it
+    RestoreGoalFully(RestoreGoalFullySignal),
     GoalComment(GoalCommentSignal),
     GoalMember(GoalMemberSignal),
     GoalVote(GoalVoteSignal),
     Member(MemberSignal),
     ProjectMeta(ProjectMetaSignal),
+    Role(RoleSignal),
 }
 
 pub fn get_peers_latest() -> ExternResult<Vec<AgentPubKey>> {
@@ -119,5 +174,111 @@ pub fn init_signal(_: ()) -> ExternResult<()> {
     let member = Member {
         address: WrappedAgentPubKey(agent_info()?.agent_initial_pubkey),
     };
-    signal_peers(&MemberSignal::new(member.clone()), get_peers_latest)
+    signal_peers(
+        &MemberSignal::new(member.clone(), ActionType::Create),
+        get_peers_latest,
+    )
+}
+
+/*
+CATCHING UP
+*/
+
+// unix timestamp in seconds, e.g. when the UI last heard a signal
+#[derive(Serialize, Deserialize, SerializedBytes)]
+pub struct FetchChangesSinceInput(pub f64);
+
+#[derive(Debug, Serialize, Deserialize, SerializedBytes)]
+pub struct VecSignalType(Vec<SignalType>);
+
+// the members who joined or were removed since the given time,
+// as the signals that were sent when they did
+fn fetch_member_changes_since(since: f64) -> ExternResult<Vec<(f64, SignalType)>> {
+    let path_hash = Path::from(MEMBER_PATH).hash()?;
+    let mut changes = Vec::new();
+    for link in get_links(path_hash, None)?.into_inner() {
+        let time = get_link_time(&link);
+        if time <= since {
+            continue;
+        }
+        if let Some(element) = get(link.target, GetOptions::content())? {
+            if let Some(member) = entry_from_element::<Member>(&element)? {
+                changes.push((
+                    time,
+                    SignalType::Member(MemberSignal::new(member, ActionType::Create)),
+                ));
+            }
+        }
+    }
+    // removals are recorded as changes of the Member entry, whose creates
+    // are left out here, as the links above already cover those
+    for (time, change) in changes::fetch_changes_since::<Member>(MEMBER_PATH, since)? {
+        if let Change::Delete(address) = change {
+            if let Some(element) = get(address, GetOptions::content())? {
+                if let Some(member) = entry_from_element::<Member>(&element)? {
+                    changes.push((
+                        time,
+                        SignalType::Member(MemberSignal::new(member, ActionType::Delete)),
+                    ));
+                }
+            }
+        }
+    }
+    Ok(changes)
+}
+
+// Everything that happened in the project since the given time, oldest first,
+// in the form of the signals that were sent for it at the time. A peer which
+// was offline can apply these the same way it would have applied the signals.
+// Role changes come as one signal per member, with the role they have now.
+// Invitations aren't included, as no signals are sent for them.
+#[hdk_extern]
+pub fn fetch_changes_since(input: FetchChangesSinceInput) -> ExternResult<VecSignalType> {
+    let since = input.0;
+    let mut changes: Vec<(f64, SignalType)> = Vec::new();
+    changes.extend(
+        inner_fetch_edge_changes_since(since)?
+            .into_iter()
+            .map(|(time, signal)| (time, SignalType::Edge(signal))),
+    );
+    changes.extend(
+        inner_fetch_entry_point_changes_since(since)?
+            .into_iter()
+            .map(|(time, signal)| (time, SignalType::EntryPoint(signal))),
+    );
+    changes.extend(
+        inner_fetch_goal_changes_since(since)?
+            .into_iter()
+            .map(|(time, signal)| (time, SignalType::Goal(signal))),
+    );
+    changes.extend(
+        inner_fetch_goal_comment_changes_since(since)?
+            .into_iter()
+            .map(|(time, signal)| (time, SignalType::GoalComment(signal))),
+    );
+    changes.extend(
+        inner_fetch_goal_member_changes_since(since)?
+            .into_iter()
+            .map(|(time, signal)| (time, SignalType::GoalMember(signal))),
+    );
+    changes.extend(
+        inner_fetch_goal_vote_changes_since(since)?
+            .into_iter()
+            .map(|(time, signal)| (time, SignalType::GoalVote(signal))),
+    );
+    changes.extend(
+        inner_fetch_project_meta_changes_since(since)?
+            .into_iter()
+            .map(|(time, signal)| (time, SignalType::ProjectMeta(signal))),
+    );
+    changes.extend(fetch_member_changes_since(since)?);
+    changes.extend(
+        fetch_role_changes_since(since)?
+            .into_iter()
+            .map(|(time, signal)| (time, SignalType::Role(signal))),
+    );
+    changes.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(std::cmp::Ordering::Equal));
+    Ok(VecSignalType(
+        changes.into_iter().map(|(_, signal)| signal).collect(),
+    ))
 }
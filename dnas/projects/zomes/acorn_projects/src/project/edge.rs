@@ -1,6 +1,13 @@
+use super::{goal::Goal, project_meta::is_tree_mode, validate::validate_edge};
 use crate::{get_peers_content, SignalType};
-use dna_help::{crud, WrappedHeaderHash};
+use dna_help::{
+    crud, signal_peers,
+    validation::{all_valid, must_be_entry, validate_entry_with},
+    AcornError, ActionType, WrappedHeaderHash,
+};
 use hdk3::prelude::*;
+use std::collections::HashSet;
+use std::fmt;
 
 // An edge. This is an arrow on the SoA Tree which directionally links
 // two goals.
@@ -16,4 +23,286 @@ fn convert_to_receiver_signal(signal: EdgeSignal) -> SignalType {
     SignalType::Edge(signal)
 }
 
-crud!(Edge, edge, "edge", get_peers_content, convert_to_receiver_signal);
+crud!(
+    Edge,
+    edge,
+    "edge",
+    get_peers_content,
+    convert_to_receiver_signal,
+    goal: [parent_address, child_address],
+    check_create: check_new_edge
+);
+
+// The reasons an edge can be refused, which would otherwise break the
+// layout of the SoA Tree. They reach the UI as the message of an
+// AcornError::ValidationFailed, displayed as just their name.
+#[derive(Serialize, Deserialize, Debug, SerializedBytes, Clone, PartialEq)]
+pub enum EdgeError {
+    SelfLoop,
+    DuplicateEdge,
+    CycleDetected,
+    // only in tree mode
+    MultipleParents,
+}
+
+impl fmt::Display for EdgeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl From<EdgeError> for AcornError {
+    fn from(edge_error: EdgeError) -> Self {
+        AcornError::ValidationFailed(edge_error.to_string())
+    }
+}
+
+impl From<EdgeError> for HdkError {
+    fn from(edge_error: EdgeError) -> Self {
+        AcornError::from(edge_error).into()
+    }
+}
+
+// Check a new edge against the rest of the tree, where `children_of`
+// gives the addresses of the goals that a goal currently has edges to.
+// The tree may already hold duplicates and cycles, made by peers who
+// hadn't seen each other's edges, so every goal is only visited once.
+pub fn find_edge_problem<F>(edge: &Edge, mut children_of: F) -> ExternResult<Option<EdgeError>>
+where
+    F: FnMut(&WrappedHeaderHash) -> ExternResult<Vec<WrappedHeaderHash>>,
+{
+    if edge.parent_address == edge.child_address {
+        return Ok(Some(EdgeError::SelfLoop));
+    }
+    if children_of(&edge.parent_address)?.contains(&edge.child_address) {
+        return Ok(Some(EdgeError::DuplicateEdge));
+    }
+    // if the parent can already be reached by following edges down from
+    // the child, then this edge would close a loop
+    let mut visited: HashSet<HeaderHash> = HashSet::new();
+    let mut to_visit = vec![edge.child_address.clone()];
+    while let Some(address) = to_visit.pop() {
+        if address == edge.parent_address {
+            return Ok(Some(EdgeError::CycleDetected));
+        }
+        if visited.insert(address.0.clone()) {
+            to_visit.extend(children_of(&address)?);
+        }
+    }
+    Ok(None)
+}
+
+// the goals which have edges to the given goal
+fn fetch_parent_addresses(
+    address: &WrappedHeaderHash,
+    get_options: GetOptions,
+) -> ExternResult<Vec<WrappedHeaderHash>> {
+    Ok(inner_fetch_edges_for_goal(address.clone(), get_options)?
+        .0
+        .into_iter()
+        .filter(|wire_entry| &wire_entry.entry.child_address == address)
+        .map(|wire_entry| wire_entry.entry.parent_address)
+        .collect())
+}
+
+// the goals which the given goal has edges to
+fn fetch_child_addresses(
+    address: &WrappedHeaderHash,
+    get_options: GetOptions,
+) -> ExternResult<Vec<WrappedHeaderHash>> {
+    Ok(inner_fetch_edges_for_goal(address.clone(), get_options)?
+        .0
+        .into_iter()
+        // the index holds edges in both directions
+        .filter(|wire_entry| &wire_entry.entry.parent_address == address)
+        .map(|wire_entry| wire_entry.entry.child_address)
+        .collect())
+}
+
+// Duplicates and cycles are only checked here, and not in validation,
+// because they depend on which other edges have been seen so far, which
+// differs between peers. An edge doesn't name the edges it was checked
+// against, so validation has no hashes to wait for as unresolved
+// dependencies either. Validation only refuses what is wrong with an edge
+// on its own, which is pointing at the goal it comes from, or at something
+// which isn't a goal.
+fn check_edge_shape(edge: &Edge) -> ExternResult<()> {
+    match find_edge_problem(edge, |address| {
+        fetch_child_addresses(address, GetOptions::latest())
+    })? {
+        Some(edge_error) => Err(edge_error.into()),
+        None => Ok(()),
+    }
+}
+
+// Tree mode is only checked here, and not in validation, because it is a
+// setting which can be changed, and edges made before it was turned on
+// should stay valid.
+pub fn check_new_edge(edge: &Edge) -> ExternResult<()> {
+    check_edge_shape(edge)?;
+    if is_tree_mode()?
+        && !fetch_parent_addresses(&edge.child_address, GetOptions::latest())?.is_empty()
+    {
+        return Err(EdgeError::MultipleParents.into());
+    }
+    Ok(())
+}
+
+#[derive(Serialize, Deserialize, Debug, SerializedBytes, Clone, PartialEq)]
+pub struct ReparentGoalInput {
+    goal_address: WrappedHeaderHash,
+    new_parent_address: WrappedHeaderHash,
+}
+
+#[derive(Serialize, Deserialize, Debug, SerializedBytes, Clone, PartialEq)]
+pub struct ReparentGoalOutput {
+    archived_edges: Vec<WrappedHeaderHash>,
+    edge: EdgeWireEntry,
+}
+
+// custom signal type
+#[derive(Debug, Serialize, Deserialize, SerializedBytes)]
+pub struct ReparentGoalSignal {
+    entry_type: String,
+    action: ActionType,
+    data: ReparentGoalOutput,
+}
+
+// Move a goal under a new parent, replacing whatever edges currently
+// point at it. This is how a goal gets a different parent in tree mode.
+#[hdk_extern]
+pub fn reparent_goal(input: ReparentGoalInput) -> ExternResult<ReparentGoalOutput> {
+    let edge = Edge {
+        parent_address: input.new_parent_address,
+        child_address: input.goal_address.clone(),
+        randomizer: sys_time()?.as_secs_f64(),
+    };
+    // the old edges are about to go, so only the shape of the
+    // tree needs checking, and not the number of parents
+    check_edge_shape(&edge)?;
+
+    let archived_edges =
+        inner_fetch_edges_for_goal(input.goal_address.clone(), GetOptions::latest())?
+            .0
+            .into_iter()
+            .filter(|wire_entry| wire_entry.entry.child_address == input.goal_address)
+            .map(|wire_entry| inner_archive_edge(wire_entry.address, false))
+            .collect::<ExternResult<Vec<WrappedHeaderHash>>>()?;
+    let edge = inner_create_edge_unchecked(edge, false)?;
+
+    let reparent_goal_output = ReparentGoalOutput {
+        archived_edges,
+        edge,
+    };
+    let signal = SignalType::ReparentGoal(ReparentGoalSignal {
+        entry_type: "reparent_goal".to_string(),
+        action: ActionType::Update,
+        data: reparent_goal_output.clone(),
+    });
+    let _ = debug!(format!("REPARENT GOAL ACTION SIGNAL PEERS {:?}", signal));
+    let _ = signal_peers(&signal, get_peers_content);
+
+    Ok(reparent_goal_output)
+}
+
+// both ends of an edge have to be goals, even if they've been archived since
+fn validate_edge_goals(edge: &Edge) -> ExternResult<ValidateCallbackResult> {
+    Ok(all_valid(vec![
+        validate_edge(edge),
+        must_be_entry::<Goal>(&edge.parent_address.0, "parent_address")?,
+        must_be_entry::<Goal>(&edge.child_address.0, "child_address")?,
+    ]))
+}
+
+#[hdk_extern]
+pub fn validate_create_entry_edge(
+    validate_data: ValidateData,
+) -> ExternResult<ValidateCallbackResult> {
+    validate_entry_with(validate_data, |edge: Edge, _: &AgentPubKey| {
+        validate_edge_goals(&edge)
+    })
+}
+
+#[hdk_extern]
+pub fn validate_update_entry_edge(
+    validate_data: ValidateData,
+) -> ExternResult<ValidateCallbackResult> {
+    validate_entry_with(validate_data, |edge: Edge, _: &AgentPubKey| {
+        validate_edge_goals(&edge)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn goal(byte: u8) -> WrappedHeaderHash {
+        WrappedHeaderHash(HeaderHash::from_raw_36(vec![byte; 36]))
+    }
+
+    fn edge(parent: u8, child: u8) -> Edge {
+        Edge {
+            parent_address: goal(parent),
+            child_address: goal(child),
+            randomizer: 0.0,
+        }
+    }
+
+    // check `new_edge` against a tree made of `edges`
+    fn problem(edges: Vec<Edge>, new_edge: Edge) -> Option<EdgeError> {
+        let mut children: HashMap<HeaderHash, Vec<WrappedHeaderHash>> = HashMap::new();
+        for edge in edges {
+            children
+                .entry(edge.parent_address.0)
+                .or_default()
+                .push(edge.child_address);
+        }
+        find_edge_problem(&new_edge, |address| {
+            Ok(children.get(&address.0).cloned().unwrap_or_default())
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn accepts_a_new_branch() {
+        assert_eq!(problem(vec![edge(1, 2)], edge(1, 3)), None);
+        assert_eq!(problem(vec![edge(1, 2), edge(3, 4)], edge(2, 3)), None);
+    }
+
+    #[test]
+    fn rejects_self_loops() {
+        assert_eq!(problem(vec![], edge(1, 1)), Some(EdgeError::SelfLoop));
+    }
+
+    #[test]
+    fn rejects_duplicates() {
+        assert_eq!(
+            problem(vec![edge(1, 2)], edge(1, 2)),
+            Some(EdgeError::DuplicateEdge)
+        );
+    }
+
+    #[test]
+    fn stops_at_cycles_already_in_the_tree() {
+        let cycle = vec![edge(1, 2), edge(2, 3), edge(3, 1)];
+        assert_eq!(problem(cycle.clone(), edge(4, 1)), None);
+        assert_eq!(
+            problem(cycle.clone(), edge(2, 1)),
+            Some(EdgeError::CycleDetected)
+        );
+        assert_eq!(problem(vec![edge(1, 2), edge(1, 2)], edge(2, 3)), None);
+    }
+
+    #[test]
+    fn rejects_cycles() {
+        assert_eq!(
+            problem(vec![edge(1, 2)], edge(2, 1)),
+            Some(EdgeError::CycleDetected)
+        );
+        assert_eq!(
+            problem(vec![edge(1, 2), edge(2, 3), edge(3, 4)], edge(4, 1)),
+            Some(EdgeError::CycleDetected)
+        );
+    }
+}
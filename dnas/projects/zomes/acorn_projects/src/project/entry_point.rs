@@ -1,5 +1,9 @@
+use super::{
+    role::{must_have_role, Role},
+    validate::validate_entry_point,
+};
 use crate::{get_peers_content, SignalType};
-use dna_help::{crud, WrappedAgentPubKey, WrappedHeaderHash};
+use dna_help::{crud, validation::validate_entry_with, WrappedAgentPubKey, WrappedHeaderHash};
 use hdk3::prelude::*;
 
 // The "Entry" in EntryPoint is not a reference to Holochain "Entries"
@@ -22,5 +26,36 @@ crud!(
     entry_point,
     "entry_point",
     get_peers_content,
-    convert_to_receiver_signal
+    convert_to_receiver_signal,
+    goal: [goal_address],
+    check_change: check_entry_point_change
 );
+
+fn check_entry_point_change(_: &WrappedHeaderHash) -> ExternResult<()> {
+    must_have_role(Role::Editor)?;
+    Ok(())
+}
+
+#[hdk_extern]
+pub fn validate_create_entry_entry_point(
+    validate_data: ValidateData,
+) -> ExternResult<ValidateCallbackResult> {
+    validate_entry_with(
+        validate_data,
+        |entry_point: EntryPoint, author: &AgentPubKey| {
+            Ok(validate_entry_point(&entry_point, author))
+        },
+    )
+}
+
+#[hdk_extern]
+pub fn validate_update_entry_entry_point(
+    validate_data: ValidateData,
+) -> ExternResult<ValidateCallbackResult> {
+    validate_entry_with(
+        validate_data,
+        |entry_point: EntryPoint, author: &AgentPubKey| {
+            Ok(validate_entry_point(&entry_point, author))
+        },
+    )
+}
@@ -1,12 +1,32 @@
 use super::{
-    edge::{inner_archive_edge, inner_create_edge, inner_fetch_edges, Edge, EdgeWireEntry},
-    entry_point::{inner_archive_entry_point, inner_fetch_entry_points, EntryPointWireEntry},
-    goal_comment::{inner_archive_goal_comment, inner_fetch_goal_comments, GoalCommentWireEntry},
-    goal_member::archive_goal_members,
-    goal_vote::{inner_archive_goal_vote, inner_fetch_goal_votes, GoalVoteWireEntry},
+    edge::{
+        inner_archive_edge, inner_create_edge, inner_fetch_edges, inner_fetch_edges_for_goal,
+        inner_reindex_edges, Edge, EdgeWireEntry,
+    },
+    entry_point::{
+        inner_archive_entry_point, inner_fetch_entry_points, inner_fetch_entry_points_for_goal,
+        inner_reindex_entry_points, EntryPointWireEntry,
+    },
+    goal_comment::{
+        inner_archive_goal_comment, inner_fetch_goal_comments, inner_fetch_goal_comments_for_goal,
+        inner_reindex_goal_comments, GoalCommentWireEntry,
+    },
+    goal_member::{archive_goal_members, inner_reindex_goal_members, GoalMember, GOAL_MEMBER_PATH},
+    goal_vote::{
+        inner_archive_goal_vote, inner_fetch_goal_votes, inner_fetch_goal_votes_for_goal,
+        inner_reindex_goal_votes, GoalVoteWireEntry,
+    },
+    role::{must_have_role, Role},
+    validate::{validate_goal_create, validate_goal_restore, validate_goal_update},
 };
 use crate::{get_peers_content, SignalType};
-use dna_help::{crud, signal_peers, ActionType, WrappedAgentPubKey, WrappedHeaderHash};
+use dna_help::{
+    crud, get_header_hash, get_header_time, has_index_links, index_path, signal_peers,
+    validation::{
+        entry_from_app_entry, entry_from_element, validate_entry_with, validate_update_with,
+    },
+    AcornError, ActionType, WrappedAgentPubKey, WrappedHeaderHash,
+};
 use hdk3::prelude::*;
 use std::fmt;
 
@@ -16,23 +36,26 @@ use std::fmt;
 #[hdk_entry(id = "goal")]
 #[derive(Debug, Clone, PartialEq)]
 pub struct Goal {
-    content: String,
-    user_hash: WrappedAgentPubKey,
-    user_edit_hash: Option<WrappedAgentPubKey>,
-    timestamp_created: f64,
-    timestamp_updated: Option<f64>,
-    hierarchy: Hierarchy,
-    status: Status,
-    tags: Option<Vec<String>>,
-    description: String,
-    time_frame: Option<TimeFrame>,
+    pub content: String,
+    pub user_hash: WrappedAgentPubKey,
+    pub user_edit_hash: Option<WrappedAgentPubKey>,
+    pub timestamp_created: f64,
+    pub timestamp_updated: Option<f64>,
+    pub hierarchy: Hierarchy,
+    pub status: Status,
+    pub tags: Option<Vec<String>>,
+    pub description: String,
+    pub time_frame: Option<TimeFrame>,
+    // the archived goal this was brought back from by restore_goal_fully
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub restored_from: Option<WrappedHeaderHash>,
 }
 
 #[derive(Debug, Serialize, Deserialize, SerializedBytes, Clone, PartialEq)]
 pub struct UIEnum(String);
 
 #[derive(Serialize, Deserialize, Debug, SerializedBytes, Clone, PartialEq)]
-#[serde(from = "UIEnum")]
+#[serde(try_from = "UIEnum")]
 #[serde(into = "UIEnum")]
 pub enum Status {
     Uncertain,
@@ -42,14 +65,16 @@ pub enum Status {
     InReview,
 }
 
-impl From<UIEnum> for Status {
-    fn from(ui_enum: UIEnum) -> Self {
+impl TryFrom<UIEnum> for Status {
+    type Error = String;
+    fn try_from(ui_enum: UIEnum) -> Result<Self, Self::Error> {
         match ui_enum.0.as_str() {
-            "Incomplete" => Self::Incomplete,
-            "InProcess" => Self::InProcess,
-            "Complete" => Self::Complete,
-            "InReview" => Self::InReview,
-            _ => Self::Uncertain,
+            "Uncertain" => Ok(Self::Uncertain),
+            "Incomplete" => Ok(Self::Incomplete),
+            "InProcess" => Ok(Self::InProcess),
+            "Complete" => Ok(Self::Complete),
+            "InReview" => Ok(Self::InReview),
+            other => Err(format!("unknown goal status {:?}", other)),
         }
     }
 }
@@ -65,7 +90,7 @@ impl fmt::Display for Status {
 }
 
 #[derive(Serialize, Deserialize, Debug, SerializedBytes, Clone, PartialEq)]
-#[serde(from = "UIEnum")]
+#[serde(try_from = "UIEnum")]
 #[serde(into = "UIEnum")]
 pub enum Hierarchy {
     Root,
@@ -74,14 +99,16 @@ pub enum Hierarchy {
     Leaf,
     NoHierarchy,
 }
-impl From<UIEnum> for Hierarchy {
-    fn from(ui_enum: UIEnum) -> Self {
+impl TryFrom<UIEnum> for Hierarchy {
+    type Error = String;
+    fn try_from(ui_enum: UIEnum) -> Result<Self, Self::Error> {
         match ui_enum.0.as_str() {
-            "Root" => Self::Root,
-            "Trunk" => Self::Trunk,
-            "Branch" => Self::Branch,
-            "Leaf" => Self::Leaf,
-            _ => Self::NoHierarchy,
+            "Root" => Ok(Self::Root),
+            "Trunk" => Ok(Self::Trunk),
+            "Branch" => Ok(Self::Branch),
+            "Leaf" => Ok(Self::Leaf),
+            "NoHierarchy" => Ok(Self::NoHierarchy),
+            other => Err(format!("unknown hierarchy {:?}", other)),
         }
     }
 }
@@ -106,7 +133,55 @@ fn convert_to_receiver_signal(signal: GoalSignal) -> SignalType {
     SignalType::Goal(signal)
 }
 
-crud!(Goal, goal, "goal", get_peers_content, convert_to_receiver_signal);
+crud!(
+    Goal,
+    goal,
+    "goal",
+    get_peers_content,
+    convert_to_receiver_signal,
+    check_change: check_goal_change
+);
+
+fn check_goal_change(_: &WrappedHeaderHash) -> ExternResult<()> {
+    must_have_role(Role::Editor)?;
+    Ok(())
+}
+
+#[hdk_extern]
+pub fn validate_create_entry_goal(
+    validate_data: ValidateData,
+) -> ExternResult<ValidateCallbackResult> {
+    validate_entry_with(validate_data, |goal: Goal, author: &AgentPubKey| {
+        let restored_from = match &goal.restored_from {
+            Some(restored_from) => restored_from.0.clone(),
+            None => return Ok(validate_goal_create(&goal, author)),
+        };
+        match get(restored_from.clone(), GetOptions::content())? {
+            // anything else on the DHT doesn't deserialize as a Goal
+            Some(element) => match entry_from_element::<Goal>(&element).ok().flatten() {
+                Some(original) => Ok(validate_goal_restore(&goal, &original, author)),
+                None => Ok(ValidateCallbackResult::Invalid(
+                    "restored_from is not a goal".into(),
+                )),
+            },
+            None => Ok(ValidateCallbackResult::UnresolvedDependencies(vec![
+                restored_from.into(),
+            ])),
+        }
+    })
+}
+
+#[hdk_extern]
+pub fn validate_update_entry_goal(
+    validate_data: ValidateData,
+) -> ExternResult<ValidateCallbackResult> {
+    validate_update_with(
+        validate_data,
+        |goal: Goal, original: Goal, author: &AgentPubKey| {
+            Ok(validate_goal_update(&goal, &original, author))
+        },
+    )
+}
 
 #[derive(Serialize, Deserialize, Debug, SerializedBytes, Clone, PartialEq)]
 pub struct CreateGoalWithEdgeInput {
@@ -164,12 +239,12 @@ pub fn create_goal_with_edge(
 
 #[derive(Serialize, Deserialize, Debug, SerializedBytes, Clone, PartialEq)]
 pub struct ArchiveGoalFullyResponse {
-    address: WrappedHeaderHash,
-    archived_edges: Vec<WrappedHeaderHash>,
-    archived_goal_members: Vec<WrappedHeaderHash>,
-    archived_goal_votes: Vec<WrappedHeaderHash>,
-    archived_goal_comments: Vec<WrappedHeaderHash>,
-    archived_entry_points: Vec<WrappedHeaderHash>,
+    pub address: WrappedHeaderHash,
+    pub archived_edges: Vec<WrappedHeaderHash>,
+    pub archived_goal_members: Vec<WrappedHeaderHash>,
+    pub archived_goal_votes: Vec<WrappedHeaderHash>,
+    pub archived_goal_comments: Vec<WrappedHeaderHash>,
+    pub archived_entry_points: Vec<WrappedHeaderHash>,
 }
 
 // custom signal type
@@ -180,76 +255,103 @@ pub struct ArchiveGoalFullySignal {
     data: ArchiveGoalFullyResponse,
 }
 
+// archive each record, leaving out any which couldn't be archived
+fn archive_each<F>(addresses: Vec<WrappedHeaderHash>, archive: F) -> Vec<WrappedHeaderHash>
+where
+    F: Fn(WrappedHeaderHash, bool) -> ExternResult<WrappedHeaderHash>,
+{
+    addresses
+        .into_iter()
+        .map(|address| archive(address, false))
+        // filter out errors
+        .filter_map(Result::ok)
+        .collect()
+}
+
 #[hdk_extern]
 pub fn archive_goal_fully(address: WrappedHeaderHash) -> ExternResult<ArchiveGoalFullyResponse> {
     inner_archive_goal(address.clone(), false)?;
 
-    let archived_edges = inner_fetch_edges(GetOptions::content())?
-        .0
-        .into_iter()
-        .filter(|wire_entry: &EdgeWireEntry| {
-            // check whether the parent_address or child_address is equal to the given address.
-            // If so, the edge is connected to the goal being archived.
-            wire_entry.entry.child_address == address.clone()
-                || wire_entry.entry.parent_address == address.clone()
-        })
-        .map(|wire_entry: EdgeWireEntry| {
-            let edge_address = wire_entry.address;
-            match inner_archive_edge(edge_address.clone(), false) {
-                Ok(_) => Ok(edge_address),
-                Err(e) => Err(e),
-            }
-        })
-        // filter out errors
-        .filter_map(Result::ok)
-        .collect();
+    // Goals whose records were all made before they were indexed have
+    // nothing in their index, so every record is checked for those instead.
+    // Records made since are found through the index, which is why
+    // `reindex_goal_links` should be run once for projects from before.
+    let indexed = has_index_links("goal", &address)?;
 
-    let archived_goal_members = archive_goal_members(address.clone())?;
+    // edges are indexed by both their parent_address and child_address
+    // so this finds every edge connected to the goal being archived
+    let edges: Vec<EdgeWireEntry> = if indexed {
+        inner_fetch_edges_for_goal(address.clone(), GetOptions::content())?.0
+    } else {
+        inner_fetch_edges(GetOptions::content())?
+            .0
+            .into_iter()
+            .filter(|wire_entry| {
+                wire_entry.entry.child_address == address
+                    || wire_entry.entry.parent_address == address
+            })
+            .collect()
+    };
+    let archived_edges = archive_each(
+        edges
+            .into_iter()
+            .map(|wire_entry| wire_entry.address)
+            .collect(),
+        inner_archive_edge,
+    );
 
-    let archived_goal_votes = inner_fetch_goal_votes(GetOptions::content())?
-        .0
-        .into_iter()
-        .filter(|wire_entry: &GoalVoteWireEntry| wire_entry.entry.goal_address == address.clone())
-        .map(|wire_entry: GoalVoteWireEntry| {
-            let goal_vote_address = wire_entry.address;
-            match inner_archive_goal_vote(goal_vote_address.clone(), false) {
-                Ok(_) => Ok(goal_vote_address),
-                Err(e) => Err(e),
-            }
-        })
-        // filter out errors
-        .filter_map(Result::ok)
-        .collect();
+    let archived_goal_members = archive_goal_members(address.clone(), indexed)?;
 
-    let archived_goal_comments = inner_fetch_goal_comments(GetOptions::content())?
-        .0
-        .into_iter()
-        .filter(|wire_entry: &GoalCommentWireEntry| wire_entry.entry.goal_address == address)
-        .map(|wire_entry: GoalCommentWireEntry| {
-            let goal_comment_address = wire_entry.address;
-            match inner_archive_goal_comment(goal_comment_address.clone(), false) {
-                Ok(_) => Ok(goal_comment_address),
-                Err(e) => Err(e),
-            }
-        })
-        // filter out errors
-        .filter_map(Result::ok)
-        .collect();
+    let goal_votes: Vec<GoalVoteWireEntry> = if indexed {
+        inner_fetch_goal_votes_for_goal(address.clone(), GetOptions::content())?.0
+    } else {
+        inner_fetch_goal_votes(GetOptions::content())?
+            .0
+            .into_iter()
+            .filter(|wire_entry| wire_entry.entry.goal_address == address)
+            .collect()
+    };
+    let archived_goal_votes = archive_each(
+        goal_votes
+            .into_iter()
+            .map(|wire_entry| wire_entry.address)
+            .collect(),
+        inner_archive_goal_vote,
+    );
 
-    let archived_entry_points = inner_fetch_entry_points(GetOptions::content())?
-        .0
-        .into_iter()
-        .filter(|wire_entry: &EntryPointWireEntry| wire_entry.entry.goal_address == address)
-        .map(|wire_entry: EntryPointWireEntry| {
-            let entry_point_address = wire_entry.address;
-            match inner_archive_entry_point(entry_point_address.clone(), false) {
-                Ok(_) => Ok(entry_point_address),
-                Err(e) => Err(e),
-            }
-        })
-        // filter out errors
-        .filter_map(Result::ok)
-        .collect();
+    let goal_comments: Vec<GoalCommentWireEntry> = if indexed {
+        inner_fetch_goal_comments_for_goal(address.clone(), GetOptions::content())?.0
+    } else {
+        inner_fetch_goal_comments(GetOptions::content())?
+            .0
+            .into_iter()
+            .filter(|wire_entry| wire_entry.entry.goal_address == address)
+            .collect()
+    };
+    let archived_goal_comments = archive_each(
+        goal_comments
+            .into_iter()
+            .map(|wire_entry| wire_entry.address)
+            .collect(),
+        inner_archive_goal_comment,
+    );
+
+    let entry_points: Vec<EntryPointWireEntry> = if indexed {
+        inner_fetch_entry_points_for_goal(address.clone(), GetOptions::content())?.0
+    } else {
+        inner_fetch_entry_points(GetOptions::content())?
+            .0
+            .into_iter()
+            .filter(|wire_entry| wire_entry.entry.goal_address == address)
+            .collect()
+    };
+    let archived_entry_points = archive_each(
+        entry_points
+            .into_iter()
+            .map(|wire_entry| wire_entry.address)
+            .collect(),
+        inner_archive_entry_point,
+    );
 
     let archive_response = ArchiveGoalFullyResponse {
         address,
@@ -274,91 +376,156 @@ pub fn archive_goal_fully(address: WrappedHeaderHash) -> ExternResult<ArchiveGoa
     Ok(archive_response)
 }
 
-// TODO: finish get goal history
+// Link the edges, members, votes, comments and entry points made before
+// they were indexed by goal from the index of their goal, so that they are
+// found with the rest. Only needs running once per project, but is safe to
+// run again. Gives back how many links were made.
+#[hdk_extern]
+pub fn reindex_goal_links(_: ()) -> ExternResult<u32> {
+    Ok(inner_reindex_edges()?
+        + inner_reindex_goal_members()?
+        + inner_reindex_goal_votes()?
+        + inner_reindex_goal_comments()?
+        + inner_reindex_entry_points()?)
+}
+
+// one version of a goal, from its original create or one of its updates
+#[derive(Serialize, Deserialize, Debug, SerializedBytes, Clone, PartialEq)]
+pub struct GoalVersion {
+    entry: Goal,
+    author: WrappedAgentPubKey,
+    timestamp: f64,
+    header_address: WrappedHeaderHash,
+}
+
 #[derive(Serialize, Deserialize, Debug, SerializedBytes, Clone)]
 pub struct GetHistoryResponse {
-    entries: Vec<Goal>,
-    members: Vec<Vec<super::goal_member::GoalMember>>,
+    // oldest first
+    entries: Vec<GoalVersion>,
+    // the goal members that were assigned at the time of
+    // the version with the same index in `entries`
+    members: Vec<Vec<GoalMember>>,
     address: WrappedHeaderHash,
 }
 
-// pub fn history_of_goal(address: WrappedHeaderHash) -> ExternResult<GetHistoryResponse> {
-//   let anchor_address = Entry::App(
-//     "anchor".into(),       // app entry type
-//     "goal_members".into(), // app entry value
-//   )
-//   .address();
-//   // return all the Goal objects from the entries linked to the edge anchor (drop entries with wrong type)
-//   let members = get_links!(
-//     &anchor_address,
-//     LinkMatch::Exactly("anchor->goal_member"), // the link type to match
-//     LinkMatch::Any,
-//   )?
-//   // scoop all these entries up into an array and return it
-//   .addresses()
-//   .into_iter()
-//   .map(|member_address: WrappedHeaderHash| {
-//     if let Ok(Some(entry_history)) = hdk::api::get_entry_history(&member_address) {
-//       Some(
-//         entry_history
-//           .items
-//           .into_iter()
-//           .map(|item| {
-//             if let Some(App(_, value_entry)) = item.entry {
-//               match serde_json::from_str::<GoalMember>(&Into::<String>::into(value_entry)).ok() {
-//                 Some(goal_member) => {
-//                   // filter down to only Goal Members that are associated with the requested Goal
-//                   if goal_member.goal_address == address {
-//                     Ok(goal_member)
-//                   } else {
-//                     Err(ZomeApiError::Internal("error".into()))
-//                   }
-//                 }
-//                 None => Err(ZomeApiError::Internal("error".into())),
-//               }
-//             } else {
-//               Err(ZomeApiError::Internal("error".into()))
-//             }
-//           })
-//           .filter_map(Result::ok)
-//           .collect(),
-//       )
-//     } else {
-//       None
-//     }
-//   })
-//   .filter_map(|op: Option<Vec<GoalMember>>| match op {
-//     Some(vec) => {
-//       if vec.len() > 0 {
-//         Some(vec)
-//       } else {
-//         None
-//       }
-//     }
-//     _ => None,
-//   })
-//   .collect();
-//   if let Ok(Some(entry_history)) = hdk::api::get_entry_history(&address) {
-//     Ok(GetHistoryResponse {
-//       entries: entry_history
-//         .items
-//         .into_iter()
-//         .map(|item| {
-//           if let Some(App(_, value_entry)) = item.entry {
-//             match serde_json::from_str::<Goal>(&Into::<String>::into(value_entry)).ok() {
-//               Some(goal) => Ok(goal),
-//               None => Err(ZomeApiError::Internal("error".into())),
-//             }
-//           } else {
-//             Err(ZomeApiError::Internal("error".into()))
-//           }
-//         })
-//         .filter_map(Result::ok)
-//         .collect(),
-//       members: members,
-//       address: address,
-//     })
-//   } else {
-//     Err(ZomeApiError::Internal("error".into()))
-//   }
-// }
+// walk the original header, and every update which follows from it
+fn goal_versions(address: HeaderHash) -> ExternResult<Vec<GoalVersion>> {
+    let mut versions: Vec<GoalVersion> = Vec::new();
+    let mut to_visit = vec![address];
+    while let Some(header_hash) = to_visit.pop() {
+        if let Some(Details::Element(details)) = get_details(header_hash, GetOptions::content())? {
+            if let Some(goal) = details.element.entry().to_app_option::<Goal>()? {
+                let header = details.element.header();
+                versions.push(GoalVersion {
+                    entry: goal,
+                    author: WrappedAgentPubKey(header.author().clone()),
+                    timestamp: get_header_time(header),
+                    header_address: WrappedHeaderHash(details.element.header_address().clone()),
+                });
+            }
+            to_visit.extend(details.updates.into_iter().map(get_header_hash));
+        }
+    }
+    versions.sort_by(|a, b| {
+        a.timestamp
+            .partial_cmp(&b.timestamp)
+            .unwrap_or(std::cmp::Ordering::Equal)
+    });
+    Ok(versions)
+}
+
+// a goal member, and the window of time during which it was assigned
+struct GoalMemberSpan {
+    goal_member: GoalMember,
+    created_at: f64,
+    deleted_at: Option<f64>,
+}
+
+// every goal member ever assigned to the goal, including ones which were since archived
+fn goal_member_spans(address: &WrappedHeaderHash) -> ExternResult<Vec<GoalMemberSpan>> {
+    let path_hash = index_path("goal", address).hash()?;
+    let links = get_links(path_hash, Some(LinkTag::new(GOAL_MEMBER_PATH)))?.into_inner();
+    let mut spans = Vec::new();
+    for link in links {
+        if let Some(Details::Entry(details)) = get_details(link.target, GetOptions::content())? {
+            let goal_member = match entry_from_app_entry::<GoalMember>(&details.entry) {
+                Some(goal_member) => goal_member,
+                None => continue,
+            };
+            for create in details.headers.iter() {
+                let create_address = create.header_address().clone();
+                let deleted_at = details
+                    .deletes
+                    .iter()
+                    .filter(|delete| match delete.header() {
+                        Header::Delete(delete) => delete.deletes_address == create_address,
+                        _ => false,
+                    })
+                    .map(|delete| get_header_time(delete.header()))
+                    .fold(None, |earliest: Option<f64>, time| match earliest {
+                        Some(earliest) if earliest <= time => Some(earliest),
+                        _ => Some(time),
+                    });
+                spans.push(GoalMemberSpan {
+                    goal_member: goal_member.clone(),
+                    created_at: get_header_time(create.header()),
+                    deleted_at,
+                });
+            }
+        }
+    }
+    Ok(spans)
+}
+
+#[hdk_extern]
+pub fn history_of_goal(address: WrappedHeaderHash) -> ExternResult<GetHistoryResponse> {
+    let entries = goal_versions(address.0.clone())?;
+    if entries.is_empty() {
+        return Err(AcornError::NotFound("no goal exists".into()).into());
+    }
+    let spans = goal_member_spans(&address)?;
+    let members = entries
+        .iter()
+        .map(|version| {
+            spans
+                .iter()
+                .filter(|span| {
+                    span.created_at <= version.timestamp
+                        && span
+                            .deleted_at
+                            .map_or(true, |deleted_at| deleted_at > version.timestamp)
+                })
+                .map(|span| span.goal_member.clone())
+                .collect()
+        })
+        .collect();
+    Ok(GetHistoryResponse {
+        entries,
+        members,
+        address,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn goal_enums_must_be_known() {
+        let ui_enum = |s: &str| UIEnum(s.into());
+        assert_eq!(Status::try_from(ui_enum("InReview")), Ok(Status::InReview));
+        assert_eq!(
+            Status::try_from(ui_enum("Uncertain")),
+            Ok(Status::Uncertain)
+        );
+        assert!(Status::try_from(ui_enum("Done")).is_err());
+        assert_eq!(
+            Hierarchy::try_from(ui_enum("NoHierarchy")),
+            Ok(Hierarchy::NoHierarchy)
+        );
+        assert!(Hierarchy::try_from(ui_enum("root")).is_err());
+        // and the same goes for deserializing them
+        let sb = SerializedBytes::try_from(ui_enum("Done")).unwrap();
+        assert!(Status::try_from(sb).is_err());
+    }
+}
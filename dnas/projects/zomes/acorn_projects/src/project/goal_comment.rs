@@ -1,5 +1,14 @@
+use super::{
+    goal::Goal,
+    role::{role_of, Role},
+    validate::validate_goal_comment,
+};
 use crate::{get_peers_content, SignalType};
-use dna_help::{crud, WrappedAgentPubKey, WrappedHeaderHash};
+use dna_help::{
+    crud,
+    validation::{entry_from_element, must_be_live_entry, validate_entry_with},
+    AcornError, WrappedAgentPubKey, WrappedHeaderHash,
+};
 use hdk3::prelude::*;
 
 #[hdk_entry(id = "goal_comment")]
@@ -20,5 +29,60 @@ crud!(
     goal_comment,
     "goal_comment",
     get_peers_content,
-    convert_to_receiver_signal
+    convert_to_receiver_signal,
+    goal: [goal_address],
+    check_change: check_goal_comment_change
 );
+
+// Comments can be archived by whoever wrote them, and by Admins.
+// Editors can also archive the comments on a goal they've archived, which is
+// what archive_goal_fully does.
+fn check_goal_comment_change(address: &WrappedHeaderHash) -> ExternResult<()> {
+    let goal_comment = match get(address.0.clone(), GetOptions::content())? {
+        Some(element) => entry_from_element::<GoalComment>(&element)?,
+        None => None,
+    };
+    let goal_comment = match goal_comment {
+        Some(goal_comment) => goal_comment,
+        None => return Err(AcornError::NotFound("no goal comment exists".into()).into()),
+    };
+    let me = agent_info()?.agent_initial_pubkey;
+    if goal_comment.agent_address.0 == me {
+        return Ok(());
+    }
+    let role = role_of(&me)?;
+    let goal_archived = must_be_live_entry::<Goal>(&goal_comment.goal_address.0, "goal_address")?
+        != ValidateCallbackResult::Valid;
+    if role >= Role::Admin || (role >= Role::Editor && goal_archived) {
+        Ok(())
+    } else {
+        Err(
+            AcornError::Unauthorized(format!("a {} can only archive their own comments", role))
+                .into(),
+        )
+    }
+}
+
+#[hdk_extern]
+pub fn validate_create_entry_goal_comment(
+    validate_data: ValidateData,
+) -> ExternResult<ValidateCallbackResult> {
+    validate_entry_with(
+        validate_data,
+        |goal_comment: GoalComment, author: &AgentPubKey| {
+            Ok(validate_goal_comment(&goal_comment, author))
+        },
+    )
+}
+
+#[hdk_extern]
+pub fn validate_update_entry_goal_comment(
+    validate_data: ValidateData,
+) -> ExternResult<ValidateCallbackResult> {
+    validate_entry_with(
+        validate_data,
+        |goal_comment: GoalComment, author: &AgentPubKey| {
+            Ok(validate_goal_comment(&goal_comment, author))
+        },
+    )
+}
@@ -1,5 +1,6 @@
+use super::validate::validate_goal_member;
 use crate::{get_peers_content, SignalType};
-use dna_help::{crud, WrappedAgentPubKey, WrappedHeaderHash};
+use dna_help::{crud, validation::validate_entry_with, WrappedAgentPubKey, WrappedHeaderHash};
 use hdk3::prelude::*;
 
 // a relationship between a Goal and an Agent
@@ -21,20 +22,52 @@ crud!(
     goal_member,
     "goal_member",
     get_peers_content,
-    convert_to_receiver_signal
+    convert_to_receiver_signal,
+    goal: [goal_address]
 );
 
+#[hdk_extern]
+pub fn validate_create_entry_goal_member(
+    validate_data: ValidateData,
+) -> ExternResult<ValidateCallbackResult> {
+    validate_entry_with(
+        validate_data,
+        |goal_member: GoalMember, author: &AgentPubKey| {
+            Ok(validate_goal_member(&goal_member, author))
+        },
+    )
+}
+
+#[hdk_extern]
+pub fn validate_update_entry_goal_member(
+    validate_data: ValidateData,
+) -> ExternResult<ValidateCallbackResult> {
+    validate_entry_with(
+        validate_data,
+        |goal_member: GoalMember, author: &AgentPubKey| {
+            Ok(validate_goal_member(&goal_member, author))
+        },
+    )
+}
+
 // DELETE
-// clear all members
-pub fn archive_goal_members(address: WrappedHeaderHash) -> ExternResult<Vec<WrappedHeaderHash>> {
-    Ok(inner_fetch_goal_members(GetOptions::content())?
-        .0
+// clear all members. goals with nothing in their index may still have
+// members from before they were indexed, so all members are checked for those
+pub fn archive_goal_members(
+    address: WrappedHeaderHash,
+    indexed: bool,
+) -> ExternResult<Vec<WrappedHeaderHash>> {
+    let goal_members = if indexed {
+        inner_fetch_goal_members_for_goal(address, GetOptions::content())?.0
+    } else {
+        inner_fetch_goal_members(GetOptions::content())?
+            .0
+            .into_iter()
+            .filter(|wire_entry: &GoalMemberWireEntry| wire_entry.entry.goal_address == address)
+            .collect()
+    };
+    Ok(goal_members
         .into_iter()
-        .filter(|wire_entry: &GoalMemberWireEntry| {
-            // check whether the parent_address or child_address is equal to the given address.
-            // If so, the edge is connected to the goal being archived.
-            wire_entry.entry.goal_address == address.clone()
-        })
         .map(|wire_entry: GoalMemberWireEntry| {
             let goal_member_address = wire_entry.address;
             // archive the edge with this address
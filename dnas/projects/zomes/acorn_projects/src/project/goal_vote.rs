@@ -1,6 +1,9 @@
+use super::{project_meta::fetch_priority_weights, validate::validate_goal_vote};
 use crate::{get_peers_content, SignalType};
-use dna_help::{crud, WrappedAgentPubKey, WrappedHeaderHash};
+use dna_help::{crud, validation::validate_entry_with, WrappedAgentPubKey, WrappedHeaderHash};
 use hdk3::prelude::*;
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
 
 #[hdk_entry(id = "goal_vote")]
 #[derive(Debug, Clone, PartialEq)]
@@ -23,5 +26,234 @@ crud!(
     goal_vote,
     "goal_vote",
     get_peers_content,
-    convert_to_receiver_signal
+    convert_to_receiver_signal,
+    goal: [goal_address]
 );
+
+#[hdk_extern]
+pub fn validate_create_entry_goal_vote(
+    validate_data: ValidateData,
+) -> ExternResult<ValidateCallbackResult> {
+    validate_entry_with(
+        validate_data,
+        |goal_vote: GoalVote, author: &AgentPubKey| Ok(validate_goal_vote(&goal_vote, author)),
+    )
+}
+
+#[hdk_extern]
+pub fn validate_update_entry_goal_vote(
+    validate_data: ValidateData,
+) -> ExternResult<ValidateCallbackResult> {
+    validate_entry_with(
+        validate_data,
+        |goal_vote: GoalVote, author: &AgentPubKey| Ok(validate_goal_vote(&goal_vote, author)),
+    )
+}
+
+/*
+  PRIORITIES
+  The votes on each goal are summed up on the way out, so that clients don't
+  have to fetch every vote. Each goal gets a score from the means of its
+  votes, weighted by the `priority_weights` on the project meta, so that
+  every member of a project sees goals ranked the same way.
+*/
+
+// How much the mean of each dimension counts towards a goal's score.
+// Each is between -1 and 1, so that a dimension can count against a goal,
+// the way effort does by default.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, SerializedBytes)]
+pub struct PriorityWeights {
+    pub urgency: f64,
+    pub importance: f64,
+    pub impact: f64,
+    pub effort: f64,
+}
+
+impl Default for PriorityWeights {
+    fn default() -> Self {
+        Self {
+            urgency: 1.0,
+            importance: 1.0,
+            impact: 1.0,
+            effort: -1.0,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, SerializedBytes)]
+pub struct VoteSummary {
+    pub mean: f64,
+    pub median: f64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, SerializedBytes)]
+pub struct GoalPriority {
+    pub goal_address: WrappedHeaderHash,
+    pub vote_count: u32,
+    pub urgency: VoteSummary,
+    pub importance: VoteSummary,
+    pub impact: VoteSummary,
+    pub effort: VoteSummary,
+    pub score: f64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, SerializedBytes)]
+pub struct VecGoalPriority(Vec<GoalPriority>);
+
+// expects at least one value
+pub fn summarize(values: &[f64]) -> VoteSummary {
+    let mut sorted = values.to_vec();
+    sorted.sort_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal));
+    let middle = sorted.len() / 2;
+    let median = if sorted.len() % 2 == 0 {
+        (sorted[middle - 1] + sorted[middle]) / 2.0
+    } else {
+        sorted[middle]
+    };
+    VoteSummary {
+        mean: sorted.iter().sum::<f64>() / sorted.len() as f64,
+        median,
+    }
+}
+
+// expects at least one vote, all on the same goal
+pub fn goal_priority(votes: &[GoalVote], weights: &PriorityWeights) -> GoalPriority {
+    let summary = |dimension: fn(&GoalVote) -> f64| {
+        summarize(&votes.iter().map(dimension).collect::<Vec<f64>>())
+    };
+    let urgency = summary(|vote| vote.urgency);
+    let importance = summary(|vote| vote.importance);
+    let impact = summary(|vote| vote.impact);
+    let effort = summary(|vote| vote.effort);
+    let score = weights.urgency * urgency.mean
+        + weights.importance * importance.mean
+        + weights.impact * impact.mean
+        + weights.effort * effort.mean;
+    GoalPriority {
+        goal_address: votes[0].goal_address.clone(),
+        vote_count: votes.len() as u32,
+        urgency,
+        importance,
+        impact,
+        effort,
+        score,
+    }
+}
+
+// highest score first, with ties broken by goal address
+pub fn rank_goal_priorities(a: &GoalPriority, b: &GoalPriority) -> Ordering {
+    b.score
+        .partial_cmp(&a.score)
+        .unwrap_or(Ordering::Equal)
+        .then_with(|| {
+            a.goal_address
+                .0
+                .to_string()
+                .cmp(&b.goal_address.0.to_string())
+        })
+}
+
+// Every goal which has been voted on, with its votes summed up, highest score first
+#[hdk_extern]
+pub fn fetch_goal_priorities(_: ()) -> ExternResult<VecGoalPriority> {
+    let weights = fetch_priority_weights()?;
+    let mut votes_by_goal: BTreeMap<String, Vec<GoalVote>> = BTreeMap::new();
+    for wire_entry in inner_fetch_goal_votes(GetOptions::latest())?.0 {
+        votes_by_goal
+            .entry(wire_entry.entry.goal_address.0.to_string())
+            .or_default()
+            .push(wire_entry.entry);
+    }
+    let mut priorities: Vec<GoalPriority> = votes_by_goal
+        .values()
+        .map(|votes| goal_priority(votes, &weights))
+        .collect();
+    priorities.sort_by(rank_goal_priorities);
+    Ok(VecGoalPriority(priorities))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn goal_address(byte: u8) -> WrappedHeaderHash {
+        WrappedHeaderHash(HeaderHash::from_raw_36(vec![byte; 36]))
+    }
+
+    fn goal_vote(urgency: f64, importance: f64, impact: f64, effort: f64) -> GoalVote {
+        GoalVote {
+            goal_address: goal_address(1),
+            urgency,
+            importance,
+            impact,
+            effort,
+            agent_address: WrappedAgentPubKey(AgentPubKey::from_raw_36(vec![2; 36])),
+            unix_timestamp: 0.0,
+        }
+    }
+
+    #[test]
+    fn summarize_finds_mean_and_median() {
+        assert_eq!(
+            summarize(&[0.5]),
+            VoteSummary {
+                mean: 0.5,
+                median: 0.5
+            }
+        );
+        assert_eq!(
+            summarize(&[1.0, 0.25, 0.25]),
+            VoteSummary {
+                mean: 0.5,
+                median: 0.25
+            }
+        );
+        // with an even number of votes, the median is between the middle two
+        assert_eq!(
+            summarize(&[1.0, 0.0, 0.2, 0.6]),
+            VoteSummary {
+                mean: 0.45,
+                median: 0.4
+            }
+        );
+    }
+
+    #[test]
+    fn score_weights_the_means() {
+        let votes = vec![goal_vote(1.0, 0.5, 0.0, 0.5), goal_vote(0.0, 0.5, 1.0, 0.0)];
+        let priority = goal_priority(&votes, &PriorityWeights::default());
+        assert_eq!(priority.vote_count, 2);
+        assert_eq!(priority.goal_address, goal_address(1));
+        // 0.5 + 0.5 + 0.5 - 0.25
+        assert_eq!(priority.score, 1.25);
+        let urgent_only = PriorityWeights {
+            urgency: 1.0,
+            importance: 0.0,
+            impact: 0.0,
+            effort: 0.0,
+        };
+        assert_eq!(goal_priority(&votes, &urgent_only).score, 0.5);
+    }
+
+    #[test]
+    fn highest_score_ranks_first() {
+        let low = goal_priority(
+            &[goal_vote(0.0, 0.0, 0.0, 1.0)],
+            &PriorityWeights::default(),
+        );
+        let mut high = goal_priority(
+            &[goal_vote(1.0, 1.0, 1.0, 0.0)],
+            &PriorityWeights::default(),
+        );
+        assert_eq!(rank_goal_priorities(&high, &low), Ordering::Less);
+        assert_eq!(rank_goal_priorities(&low, &high), Ordering::Greater);
+        high.score = low.score;
+        high.goal_address = goal_address(3);
+        let expected = low
+            .goal_address
+            .0
+            .to_string()
+            .cmp(&high.goal_address.0.to_string());
+        assert_eq!(rank_goal_priorities(&low, &high), expected);
+    }
+}
@@ -0,0 +1,639 @@
+use super::role::{
+    can_assign, fetch_my_authority, member_entry_hash, must_have_role, role_from_authority, Role,
+};
+use dna_help::{
+    create_index_link, fetch_index_links, fetch_links, fetch_links_with_tag, get_header_time,
+    index_path,
+    validation::{
+        all_valid, entry_from_app_entry, entry_from_element, must_be_author, validate_entry_with,
+    },
+    AcornError, EntryAndHash, WrappedAgentPubKey, WrappedHeaderHash,
+};
+use hdk3::prelude::*;
+
+pub const INVITATION_PATH: &str = "invitation";
+// the tag of the links to a redemption, from the index of its
+// invitation, and from the Member entry of whoever redeemed it
+pub const REDEMPTION_TAG: &str = "invitation_redemption";
+
+// An invitation to join the project, with the role that comes with it.
+// Only a hash of its code is kept here, so that reading the invitation
+// from the DHT isn't enough to be able to use it.
+#[hdk_entry(id = "invitation")]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Invitation {
+    pub created_by: WrappedAgentPubKey,
+    // what gives `created_by` their role, as for role assignments
+    pub authority: Option<WrappedHeaderHash>,
+    // if given, nobody else can use the invitation
+    pub invitee: Option<WrappedAgentPubKey>,
+    pub role: Role,
+    pub expires_at: Option<f64>,
+    pub max_uses: u32,
+    // the hash of the code, which whoever made the invitation
+    // checks the code against before approving a redemption
+    pub code_hash: String,
+    pub created_at: f64,
+}
+
+// A record of an agent using an invitation. Nothing about the code is kept,
+// as anything kept here could be copied into the redemption of someone else.
+// Instead it keeps the approval of whoever made the invitation, which only
+// works for the agent it was made for.
+#[hdk_entry(id = "invitation_redemption")]
+#[derive(Debug, Clone, PartialEq)]
+pub struct InvitationRedemption {
+    pub invitation_address: WrappedHeaderHash,
+    pub agent_address: WrappedAgentPubKey,
+    pub approval: Signature,
+    pub redeemed_at: f64,
+}
+
+// What whoever made an invitation signs to let one agent redeem it.
+#[derive(Serialize, Deserialize, Debug, SerializedBytes, Clone, PartialEq)]
+pub struct RedemptionApproval {
+    pub invitation_address: WrappedHeaderHash,
+    pub agent_address: WrappedAgentPubKey,
+}
+
+// What gets handed to the invitee. It can be redeemed with
+// redeem_invitation, or given as the membrane proof when joining.
+#[derive(Serialize, Deserialize, Debug, SerializedBytes, Clone, PartialEq)]
+pub struct InvitationCode {
+    pub invitation_address: WrappedHeaderHash,
+    pub code: String,
+    // For invitations with an invitee, the approval for them, made along
+    // with the invitation. Without it, redeeming asks whoever made the
+    // invitation for one, and the code can't be used as a membrane proof.
+    #[serde(default)]
+    pub approval: Option<Signature>,
+}
+
+#[derive(Serialize, Deserialize, Debug, SerializedBytes, Clone, PartialEq)]
+pub struct ApproveRedemptionInput {
+    invitation_code: InvitationCode,
+    agent_address: WrappedAgentPubKey,
+}
+
+#[derive(Serialize, Deserialize, Debug, SerializedBytes, Clone, PartialEq)]
+pub struct InvitationWireEntry {
+    pub entry: Invitation,
+    pub address: WrappedHeaderHash,
+}
+
+impl From<EntryAndHash<Invitation>> for InvitationWireEntry {
+    fn from(entry_and_hash: EntryAndHash<Invitation>) -> Self {
+        InvitationWireEntry {
+            entry: entry_and_hash.0,
+            address: WrappedHeaderHash(entry_and_hash.1),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, SerializedBytes, Clone, PartialEq)]
+pub struct InvitationRedemptionWireEntry {
+    pub entry: InvitationRedemption,
+    pub address: WrappedHeaderHash,
+}
+
+impl From<EntryAndHash<InvitationRedemption>> for InvitationRedemptionWireEntry {
+    fn from(entry_and_hash: EntryAndHash<InvitationRedemption>) -> Self {
+        InvitationRedemptionWireEntry {
+            entry: entry_and_hash.0,
+            address: WrappedHeaderHash(entry_and_hash.1),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, SerializedBytes, Clone, PartialEq)]
+pub struct CreateInvitationInput {
+    invitee: Option<WrappedAgentPubKey>,
+    role: Role,
+    expires_at: Option<f64>,
+    max_uses: u32,
+}
+
+#[derive(Serialize, Deserialize, Debug, SerializedBytes, Clone, PartialEq)]
+pub struct CreateInvitationOutput {
+    invitation: InvitationWireEntry,
+    // this is the only time the code is given out
+    code: InvitationCode,
+}
+
+#[derive(Serialize, Deserialize, Debug, SerializedBytes, Clone, PartialEq)]
+pub struct InvitationListing {
+    invitation: InvitationWireEntry,
+    // who joined through the invitation, and when
+    redemptions: Vec<InvitationRedemptionWireEntry>,
+}
+
+#[derive(Serialize, Deserialize, Debug, SerializedBytes, Clone, PartialEq)]
+pub struct VecInvitationListing(Vec<InvitationListing>);
+
+pub fn hash_invitation_code(code: &str) -> String {
+    blake2b_simd::Params::new()
+        .hash_length(32)
+        .hash(code.as_bytes())
+        .to_hex()
+        .to_string()
+}
+
+pub fn check_code(invitation: &Invitation, code: &str) -> Result<(), String> {
+    if hash_invitation_code(code) == invitation.code_hash {
+        Ok(())
+    } else {
+        Err("the invitation code is wrong".into())
+    }
+}
+
+// Whether `agent` can use the invitation at time `at`, given how many
+// times it has been used already. Pass no `uses` to leave that out.
+pub fn check_redemption(
+    invitation: &Invitation,
+    agent: &AgentPubKey,
+    at: f64,
+    uses: Option<usize>,
+) -> Result<(), String> {
+    if let Some(invitee) = &invitation.invitee {
+        if &invitee.0 != agent {
+            return Err("the invitation is for someone else".into());
+        }
+    }
+    if let Some(expires_at) = invitation.expires_at {
+        if at > expires_at {
+            return Err("the invitation has expired".into());
+        }
+    }
+    match uses {
+        Some(uses) if uses >= invitation.max_uses as usize => {
+            Err("the invitation has been used up".into())
+        }
+        _ => Ok(()),
+    }
+}
+
+// the invitation at `address`, as long as it hasn't been revoked
+fn get_live_invitation(address: &WrappedHeaderHash) -> ExternResult<Option<Invitation>> {
+    match get_details(address.0.clone(), GetOptions::content())? {
+        Some(Details::Element(details)) if details.deletes.is_empty() => {
+            entry_from_element::<Invitation>(&details.element)
+        }
+        _ => Ok(None),
+    }
+}
+
+fn fetch_redemptions(
+    invitation_address: &WrappedHeaderHash,
+) -> ExternResult<Vec<InvitationRedemptionWireEntry>> {
+    fetch_index_links::<InvitationRedemption, InvitationRedemptionWireEntry>(
+        INVITATION_PATH,
+        invitation_address,
+        REDEMPTION_TAG,
+        GetOptions::content(),
+    )
+}
+
+// the invitation at the address, or what validating something
+// which names it should come to when there isn't one
+fn get_invitation(
+    address: &WrappedHeaderHash,
+) -> ExternResult<Result<Invitation, ValidateCallbackResult>> {
+    match get(address.0.clone(), GetOptions::content())? {
+        Some(element) => match entry_from_element::<Invitation>(&element).ok().flatten() {
+            Some(invitation) => Ok(Ok(invitation)),
+            None => Ok(Err(ValidateCallbackResult::Invalid(
+                "invitation_address is not an invitation".into(),
+            ))),
+        },
+        None => Ok(Err(ValidateCallbackResult::UnresolvedDependencies(vec![
+            address.0.clone().into(),
+        ]))),
+    }
+}
+
+fn must_be_approved(
+    invitation_address: &WrappedHeaderHash,
+    invitation: &Invitation,
+    approval: Signature,
+    agent: &AgentPubKey,
+) -> ExternResult<ValidateCallbackResult> {
+    let approved = verify_signature(
+        invitation.created_by.0.clone(),
+        approval,
+        RedemptionApproval {
+            invitation_address: invitation_address.clone(),
+            agent_address: WrappedAgentPubKey(agent.clone()),
+        },
+    )?;
+    if approved {
+        Ok(ValidateCallbackResult::Valid)
+    } else {
+        Ok(ValidateCallbackResult::Invalid(
+            "the invitation wasn't approved for this agent by whoever made it".into(),
+        ))
+    }
+}
+
+// Validation checks the approval against the invitation as it was made.
+// Whether it was revoked or used up since can only be seen from the deletes
+// and links on the DHT, which differ from peer to peer, so those are left
+// to whoever made the invitation, who checks them before approving.
+pub fn must_be_redeemable(
+    invitation_address: &WrappedHeaderHash,
+    approval: Signature,
+    agent: &AgentPubKey,
+    at: f64,
+) -> ExternResult<ValidateCallbackResult> {
+    let invitation = match get_invitation(invitation_address)? {
+        Ok(invitation) => invitation,
+        Err(result) => return Ok(result),
+    };
+    if let Err(reason) = check_redemption(&invitation, agent, at, None) {
+        return Ok(ValidateCallbackResult::Invalid(reason));
+    }
+    must_be_approved(invitation_address, &invitation, approval, agent)
+}
+
+// the role of the invitation at the address, which stays
+// the same even if the invitation is revoked later
+pub fn invitation_role(address: &WrappedHeaderHash) -> ExternResult<Option<Role>> {
+    match get(address.0.clone(), GetOptions::content())? {
+        Some(element) => Ok(entry_from_element::<Invitation>(&element)
+            .ok()
+            .flatten()
+            .map(|invitation| invitation.role)),
+        None => Ok(None),
+    }
+}
+
+// the invitations the agent redeemed, as linked from their Member entry
+pub fn fetch_redemptions_of(
+    agent_address: &AgentPubKey,
+) -> ExternResult<Vec<InvitationRedemptionWireEntry>> {
+    let mut redemptions =
+        fetch_links_with_tag::<InvitationRedemption, InvitationRedemptionWireEntry>(
+            member_entry_hash(agent_address)?,
+            Some(LinkTag::new(REDEMPTION_TAG)),
+            GetOptions::content(),
+        )?;
+    redemptions.retain(|redemption| &redemption.entry.agent_address.0 == agent_address);
+    Ok(redemptions)
+}
+
+// the roles of the invitations the agent redeemed, each with its redemption
+pub fn fetch_redeemed_roles(
+    agent_address: &AgentPubKey,
+) -> ExternResult<Vec<(WrappedHeaderHash, Role)>> {
+    let mut roles = Vec::new();
+    for redemption in fetch_redemptions_of(agent_address)? {
+        if let Some(role) = invitation_role(&redemption.entry.invitation_address)? {
+            roles.push((redemption.address, role));
+        }
+    }
+    Ok(roles)
+}
+
+#[hdk_extern]
+pub fn create_invitation(input: CreateInvitationInput) -> ExternResult<CreateInvitationOutput> {
+    let my_role = must_have_role(Role::Admin)?;
+    if !can_assign(&my_role, &input.role) {
+        return Err(AcornError::Unauthorized(format!(
+            "a {} can't invite a {}",
+            my_role, input.role
+        ))
+        .into());
+    }
+    let code: String = random_bytes(16)?
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+    let me = agent_info()?.agent_initial_pubkey;
+    let invitation = Invitation {
+        created_by: WrappedAgentPubKey(me.clone()),
+        authority: fetch_my_authority(&me)?,
+        invitee: input.invitee,
+        role: input.role,
+        expires_at: input.expires_at,
+        max_uses: input.max_uses,
+        code_hash: hash_invitation_code(&code),
+        created_at: sys_time()?.as_secs_f64(),
+    };
+    let address = WrappedHeaderHash(create_entry(&invitation)?);
+    let path = Path::from(INVITATION_PATH);
+    path.ensure()?;
+    create_link(path.hash()?, hash_entry(&invitation)?, ())?;
+    let approval = match &invitation.invitee {
+        Some(invitee) => Some(sign(
+            me,
+            RedemptionApproval {
+                invitation_address: address.clone(),
+                agent_address: invitee.clone(),
+            },
+        )?),
+        None => None,
+    };
+    Ok(CreateInvitationOutput {
+        invitation: InvitationWireEntry {
+            entry: invitation,
+            address: address.clone(),
+        },
+        code: InvitationCode {
+            invitation_address: address,
+            code,
+            approval,
+        },
+    })
+}
+
+#[hdk_extern]
+pub fn list_invitations(_: ()) -> ExternResult<VecInvitationListing> {
+    must_have_role(Role::Admin)?;
+    let path_hash = Path::from(INVITATION_PATH).hash()?;
+    let invitations =
+        fetch_links::<Invitation, InvitationWireEntry>(path_hash, GetOptions::latest())?;
+    let mut listings = Vec::new();
+    for invitation in invitations {
+        listings.push(InvitationListing {
+            redemptions: fetch_redemptions(&invitation.address)?,
+            invitation,
+        });
+    }
+    Ok(VecInvitationListing(listings))
+}
+
+// Revoked invitations can't be used anymore, but the
+// roles of those who already joined through them stay.
+#[hdk_extern]
+pub fn revoke_invitation(address: WrappedHeaderHash) -> ExternResult<WrappedHeaderHash> {
+    must_have_role(Role::Admin)?;
+    delete_entry(address.0.clone())?;
+    Ok(address)
+}
+
+// Lets any agent with the code of an invitation I made ask me to approve
+// their redemption of it. Called from init.
+pub fn create_approve_redemption_cap_grant() -> ExternResult<()> {
+    let mut functions: GrantedFunctions = HashSet::new();
+    functions.insert((zome_info()?.zome_name, "approve_redemption".into()));
+    create_cap_grant(CapGrantEntry {
+        tag: "".into(),
+        // empty access converts to unrestricted
+        access: ().into(),
+        functions,
+    })?;
+    Ok(())
+}
+
+fn inner_approve_redemption(
+    invitation_code: &InvitationCode,
+    agent_address: &AgentPubKey,
+) -> ExternResult<Signature> {
+    let me = agent_info()?.agent_initial_pubkey;
+    let invitation = match get_live_invitation(&invitation_code.invitation_address)? {
+        Some(invitation) => invitation,
+        None => {
+            return Err(
+                AcornError::NotFound("the invitation doesn't exist, or was revoked".into()).into(),
+            )
+        }
+    };
+    if invitation.created_by.0 != me {
+        return Err(AcornError::Unauthorized(
+            "only whoever made the invitation can approve its redemption".into(),
+        )
+        .into());
+    }
+    let uses = fetch_redemptions(&invitation_code.invitation_address)?.len();
+    let now = sys_time()?.as_secs_f64();
+    check_code(&invitation, &invitation_code.code)
+        .and_then(|()| check_redemption(&invitation, agent_address, now, Some(uses)))
+        .map_err(AcornError::Unauthorized)?;
+    Ok(sign(
+        me,
+        RedemptionApproval {
+            invitation_address: invitation_code.invitation_address.clone(),
+            agent_address: WrappedAgentPubKey(agent_address.clone()),
+        },
+    )?)
+}
+
+// Called on the cell of whoever made the invitation, by an agent who wants
+// to redeem it. This is where its code, revocation and uses are checked.
+#[hdk_extern]
+pub fn approve_redemption(input: ApproveRedemptionInput) -> ExternResult<Signature> {
+    inner_approve_redemption(&input.invitation_code, &input.agent_address.0)
+}
+
+pub fn inner_redeem_invitation(
+    invitation_code: InvitationCode,
+) -> ExternResult<InvitationRedemptionWireEntry> {
+    let me = agent_info()?.agent_initial_pubkey;
+    let invitation = match get_live_invitation(&invitation_code.invitation_address)? {
+        Some(invitation) => invitation,
+        None => {
+            return Err(
+                AcornError::NotFound("the invitation doesn't exist, or was revoked".into()).into(),
+            )
+        }
+    };
+    let approval = match invitation_code.approval.clone() {
+        // made along with the invitation, so its uses are counted here
+        Some(approval) => {
+            let uses = fetch_redemptions(&invitation_code.invitation_address)?.len();
+            let now = sys_time()?.as_secs_f64();
+            check_redemption(&invitation, &me, now, Some(uses))
+                .map_err(AcornError::Unauthorized)?;
+            approval
+        }
+        None if invitation.created_by.0 == me => inner_approve_redemption(&invitation_code, &me)?,
+        None => call_remote(
+            invitation.created_by.0.clone(),
+            zome_info()?.zome_name,
+            "approve_redemption".into(),
+            None,
+            ApproveRedemptionInput {
+                invitation_code: invitation_code.clone(),
+                agent_address: WrappedAgentPubKey(me.clone()),
+            },
+        )?,
+    };
+    let redemption = InvitationRedemption {
+        invitation_address: invitation_code.invitation_address,
+        agent_address: WrappedAgentPubKey(me.clone()),
+        approval,
+        redeemed_at: sys_time()?.as_secs_f64(),
+    };
+    let address = WrappedHeaderHash(create_entry(&redemption)?);
+    let entry_hash = hash_entry(&redemption)?;
+    create_index_link(
+        INVITATION_PATH,
+        &redemption.invitation_address,
+        entry_hash.clone(),
+        REDEMPTION_TAG,
+    )?;
+    create_link(
+        member_entry_hash(&me)?,
+        entry_hash,
+        LinkTag::new(REDEMPTION_TAG),
+    )?;
+    Ok(InvitationRedemptionWireEntry {
+        entry: redemption,
+        address,
+    })
+}
+
+// For members who joined without the invitation as their membrane proof.
+#[hdk_extern]
+pub fn redeem_invitation(
+    invitation_code: InvitationCode,
+) -> ExternResult<InvitationRedemptionWireEntry> {
+    inner_redeem_invitation(invitation_code)
+}
+
+#[hdk_extern]
+pub fn validate_create_entry_invitation(
+    validate_data: ValidateData,
+) -> ExternResult<ValidateCallbackResult> {
+    validate_entry_with(
+        validate_data,
+        |invitation: Invitation, author: &AgentPubKey| {
+            let author_role = match role_from_authority(&invitation.authority, author)? {
+                Ok(role) => role,
+                Err(result) => return Ok(result),
+            };
+            Ok(all_valid(vec![
+                must_be_author(author, &invitation.created_by, "created_by"),
+                if author_role >= Role::Admin && can_assign(&author_role, &invitation.role) {
+                    ValidateCallbackResult::Valid
+                } else {
+                    ValidateCallbackResult::Invalid(format!(
+                        "a {} can't invite a {}",
+                        author_role, invitation.role
+                    ))
+                },
+            ]))
+        },
+    )
+}
+
+#[hdk_extern]
+pub fn validate_create_entry_invitation_redemption(
+    validate_data: ValidateData,
+) -> ExternResult<ValidateCallbackResult> {
+    let redeemed_at = get_header_time(validate_data.element.header());
+    validate_entry_with(
+        validate_data,
+        |redemption: InvitationRedemption, author: &AgentPubKey| {
+            Ok(all_valid(vec![
+                must_be_author(author, &redemption.agent_address, "agent_address"),
+                must_be_redeemable(
+                    &redemption.invitation_address,
+                    redemption.approval,
+                    author,
+                    redeemed_at,
+                )?,
+            ]))
+        },
+    )
+}
+
+// Redemption links have to be made by whoever redeemed the invitation, from
+// their own Member entry or the index of the invitation, so that nobody can
+// take the role of an invitation by linking someone else's redemption.
+pub fn validate_redemption_link(
+    validate_data: ValidateCreateLinkData,
+) -> ExternResult<ValidateLinkCallbackResult> {
+    let redemption = match entry_from_app_entry::<InvitationRedemption>(&validate_data.target) {
+        Some(redemption) => redemption,
+        None => {
+            return Ok(ValidateLinkCallbackResult::Invalid(
+                "redemption links must point at an invitation redemption".into(),
+            ))
+        }
+    };
+    let author = validate_data.link_add.author;
+    if redemption.agent_address.0 != author {
+        return Ok(ValidateLinkCallbackResult::Invalid(
+            "a redemption can only be linked by whoever redeemed the invitation".into(),
+        ));
+    }
+    let base = hash_entry(&validate_data.base)?;
+    if base != member_entry_hash(&author)?
+        && base != index_path(INVITATION_PATH, &redemption.invitation_address).hash()?
+    {
+        return Ok(ValidateLinkCallbackResult::Invalid(
+            "a redemption can only be linked from its agent's Member entry, or its invitation's index"
+                .into(),
+        ));
+    }
+    let invitation = match get_invitation(&redemption.invitation_address)? {
+        Ok(invitation) => invitation,
+        Err(ValidateCallbackResult::UnresolvedDependencies(dependencies)) => {
+            return Ok(ValidateLinkCallbackResult::UnresolvedDependencies(
+                dependencies,
+            ))
+        }
+        Err(_) => {
+            return Ok(ValidateLinkCallbackResult::Invalid(
+                "invitation_address is not an invitation".into(),
+            ))
+        }
+    };
+    match must_be_approved(
+        &redemption.invitation_address,
+        &invitation,
+        redemption.approval,
+        &author,
+    )? {
+        ValidateCallbackResult::Valid => Ok(ValidateLinkCallbackResult::Valid),
+        _ => Ok(ValidateLinkCallbackResult::Invalid(
+            "the invitation wasn't approved for this agent by whoever made it".into(),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn agent(byte: u8) -> AgentPubKey {
+        AgentPubKey::from_raw_36(vec![byte; 36])
+    }
+
+    fn invitation(invitee: Option<AgentPubKey>, expires_at: Option<f64>) -> Invitation {
+        Invitation {
+            created_by: WrappedAgentPubKey(agent(9)),
+            authority: None,
+            invitee: invitee.map(WrappedAgentPubKey),
+            role: Role::Editor,
+            expires_at,
+            max_uses: 1,
+            code_hash: hash_invitation_code("secret"),
+            created_at: 0.0,
+        }
+    }
+
+    #[test]
+    fn needs_the_right_code() {
+        assert_eq!(check_code(&invitation(None, None), "secret"), Ok(()));
+        assert!(check_code(&invitation(None, None), "guess").is_err());
+        // the hash that is kept on the DHT isn't the code
+        assert!(check_code(&invitation(None, None), &hash_invitation_code("secret")).is_err());
+    }
+
+    #[test]
+    fn respects_invitee_expiry_and_uses() {
+        let for_agent_1 = invitation(Some(agent(1)), None);
+        assert!(check_redemption(&for_agent_1, &agent(1), 10.0, Some(0)).is_ok());
+        assert!(check_redemption(&for_agent_1, &agent(2), 10.0, Some(0)).is_err());
+
+        let expiring = invitation(None, Some(100.0));
+        assert!(check_redemption(&expiring, &agent(1), 100.0, Some(0)).is_ok());
+        assert!(check_redemption(&expiring, &agent(1), 100.5, Some(0)).is_err());
+
+        let single_use = invitation(None, None);
+        assert!(check_redemption(&single_use, &agent(1), 10.0, Some(1)).is_err());
+        // when uses aren't being counted
+        assert!(check_redemption(&single_use, &agent(1), 10.0, None).is_ok());
+    }
+}
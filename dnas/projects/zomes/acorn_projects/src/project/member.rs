@@ -1,10 +1,23 @@
-use dna_help::{fetch_links, ActionType, EntryAndHash, WrappedAgentPubKey};
+use super::{
+    invitation::{must_be_redeemable, InvitationCode},
+    role::{can_assign, role_of, Role},
+    validate::validate_member,
+};
+use crate::{get_peers_latest, SignalType};
+use dna_help::{
+    changes::record_change,
+    fetch_links, get_header_time, signal_peers,
+    validation::{all_valid, validate_entry_with},
+    AcornError, ActionType, EntryAndHash, WrappedAgentPubKey,
+};
 use hdk3::prelude::*;
 
 pub const MEMBER_PATH: &str = "member";
 
-// This is a reference to the agent address for any users who have joined this DHT
-#[hdk_entry(id = "member")]
+// This is a reference to the agent address for any users who have joined this DHT.
+// Validating it needs the author's whole source chain, which is where
+// the membrane proof they joined with is kept.
+#[hdk_entry(id = "member", required_validation_type = "full")]
 #[derive(Debug, Clone, PartialEq)]
 pub struct Member {
     pub address: WrappedAgentPubKey,
@@ -27,10 +40,10 @@ pub struct MemberSignal {
 }
 
 impl MemberSignal {
-    pub fn new(member: Member) -> Self {
+    pub fn new(member: Member, action: ActionType) -> Self {
         Self {
             entry_type: "member".to_string(),
-            action: ActionType::Create,
+            action,
             data: member,
         }
     }
@@ -42,3 +55,254 @@ pub fn fetch_members(_: ()) -> ExternResult<VecMember> {
     let entries = fetch_links::<Member, Member>(path_hash, GetOptions::content())?;
     Ok(VecMember(entries))
 }
+
+// Members can remove themselves, and Admins and Owners can remove
+// anyone whose role they would be allowed to assign.
+pub fn can_remove(remover_role: &Role, member_role: &Role) -> bool {
+    remover_role >= &Role::Admin && can_assign(remover_role, member_role)
+}
+
+// Takes the member out of the list of members, so that they stop being
+// one of the peers that signals are sent to.
+fn inner_remove_member(member: Member) -> ExternResult<Member> {
+    // tell everyone, including the member being removed,
+    // while they are still one of the peers
+    let signal = SignalType::Member(MemberSignal::new(member.clone(), ActionType::Delete));
+    let _ = signal_peers(&signal, get_peers_latest);
+    let member_entry_hash = hash_entry(&member)?;
+    // so that fetch_changes_since can find the removal
+    record_change(MEMBER_PATH, member_entry_hash.clone())?;
+    let path_hash = Path::from(MEMBER_PATH).hash()?;
+    for link in get_links(path_hash, None)?.into_inner() {
+        if link.target == member_entry_hash {
+            delete_link(link.create_link_hash)?;
+        }
+    }
+    if let Some(Details::Entry(details)) = get_details(member_entry_hash, GetOptions::content())? {
+        for create in details.headers.iter() {
+            let deleted = details.deletes.iter().any(|delete| match delete.header() {
+                Header::Delete(delete) => &delete.deletes_address == create.header_address(),
+                _ => false,
+            });
+            if !deleted {
+                delete_entry(create.header_address().clone())?;
+            }
+        }
+    }
+    Ok(member)
+}
+
+#[hdk_extern]
+pub fn leave_project(_: ()) -> ExternResult<Member> {
+    inner_remove_member(Member {
+        address: WrappedAgentPubKey(agent_info()?.agent_initial_pubkey),
+    })
+}
+
+#[hdk_extern]
+pub fn remove_member(agent_address: WrappedAgentPubKey) -> ExternResult<Member> {
+    let my_role = role_of(&agent_info()?.agent_initial_pubkey)?;
+    let member_role = role_of(&agent_address.0)?;
+    if !can_remove(&my_role, &member_role) {
+        return Err(AcornError::Unauthorized(format!(
+            "a {} can't remove a {}",
+            my_role, member_role
+        ))
+        .into());
+    }
+    inner_remove_member(Member {
+        address: agent_address,
+    })
+}
+
+/*
+  MEMBRANE
+  A project with a creator in its DNA properties can only be joined by agents
+  who install it with a membrane proof the creator signed for them, or with the
+  code of an invitation made for them. Everyone who joins has the DNA, so nothing in its properties
+  can be kept secret. The creator's key can be public though, as it is only used
+  to check signatures that nobody else is able to make.
+  There's no genesis self check to refuse the proof up front, but the Member
+  entry made in init can't be validated without it, so init fails instead.
+*/
+
+#[derive(Serialize, Deserialize, Debug, SerializedBytes, Default)]
+pub struct ProjectProperties {
+    // the agent who made the project, and so its Owner. Every peer installs
+    // the DNA with the same properties, so unlike anything on the DHT,
+    // this is the same for everyone and can't be changed later.
+    // When there isn't one, anyone with the DNA can join.
+    #[serde(default)]
+    pub creator: Option<WrappedAgentPubKey>,
+}
+
+pub fn project_properties() -> ExternResult<ProjectProperties> {
+    // projects made before properties were used have none
+    Ok(ProjectProperties::try_from(zome_info()?.properties).unwrap_or_default())
+}
+
+// The membrane proof for an agent: the creator's signature of their key,
+// so that it only works for them.
+#[derive(Serialize, Deserialize, Debug, SerializedBytes, Clone, PartialEq)]
+pub struct JoiningProof {
+    pub signature: Signature,
+}
+
+// For the creator to hand to someone they want to let in.
+#[hdk_extern]
+pub fn create_joining_proof(agent_address: WrappedAgentPubKey) -> ExternResult<JoiningProof> {
+    let me = agent_info()?.agent_initial_pubkey;
+    if project_properties()?.creator.map(|creator| creator.0) != Some(me.clone()) {
+        return Err(AcornError::Unauthorized(
+            "only the creator of the project can sign membrane proofs".into(),
+        )
+        .into());
+    }
+    Ok(JoiningProof {
+        signature: sign(me, agent_address)?,
+    })
+}
+
+pub fn check_joining_proof(
+    creator: &AgentPubKey,
+    joining_proof: Option<JoiningProof>,
+    agent: &AgentPubKey,
+) -> ExternResult<ValidateCallbackResult> {
+    // the creator doesn't need to let themselves in
+    if creator == agent {
+        return Ok(ValidateCallbackResult::Valid);
+    }
+    let joining_proof = match joining_proof {
+        Some(joining_proof) => joining_proof,
+        None => {
+            return Ok(ValidateCallbackResult::Invalid(
+                "joining this project needs a membrane proof".into(),
+            ))
+        }
+    };
+    if verify_signature(
+        creator.clone(),
+        joining_proof.signature,
+        WrappedAgentPubKey(agent.clone()),
+    )? {
+        Ok(ValidateCallbackResult::Valid)
+    } else {
+        Ok(ValidateCallbackResult::Invalid(
+            "the membrane proof was not signed by the creator of the project".into(),
+        ))
+    }
+}
+
+fn membrane_proof_from_chain(elements: &[Element]) -> Option<MembraneProof> {
+    elements
+        .iter()
+        .find_map(|element| match element.header() {
+            Header::AgentValidationPkg(agent_validation_pkg) => {
+                Some(agent_validation_pkg.membrane_proof.clone())
+            }
+            _ => None,
+        })
+        .flatten()
+}
+
+// the invitation code this agent joined with, if they used one as their membrane proof
+pub fn my_invitation_code() -> ExternResult<Option<InvitationCode>> {
+    let elements = query(QueryFilter::new().header_type(HeaderType::AgentValidationPkg))?.0;
+    Ok(membrane_proof_from_chain(&elements)
+        .and_then(|membrane_proof| InvitationCode::try_from(membrane_proof).ok()))
+}
+
+// the membrane proof the author joined with, which is found
+// in the AgentValidationPkg near the start of their source chain.
+// It can be signed by the creator, or be an invitation code.
+fn must_have_joined_with_membrane_proof(
+    validation_package: Option<ValidationPackage>,
+    author: &AgentPubKey,
+    joined_at: f64,
+) -> ExternResult<ValidateCallbackResult> {
+    let creator = match project_properties()?.creator {
+        Some(creator) => creator.0,
+        None => return Ok(ValidateCallbackResult::Valid),
+    };
+    let elements = match validation_package {
+        Some(validation_package) => validation_package.0,
+        None => {
+            return Ok(ValidateCallbackResult::Invalid(
+                "the author's source chain is needed to check their membrane proof".into(),
+            ))
+        }
+    };
+    let membrane_proof = membrane_proof_from_chain(&elements);
+    if let Some(invitation_code) = membrane_proof
+        .clone()
+        .and_then(|membrane_proof| InvitationCode::try_from(membrane_proof).ok())
+    {
+        // its uses are counted when it's redeemed, in init
+        return match invitation_code.approval {
+            Some(approval) => must_be_redeemable(
+                &invitation_code.invitation_address,
+                approval,
+                author,
+                joined_at,
+            ),
+            None => Ok(ValidateCallbackResult::Invalid(
+                "joining with an invitation needs it to be approved for the joining agent".into(),
+            )),
+        };
+    }
+    check_joining_proof(
+        &creator,
+        membrane_proof.and_then(|membrane_proof| JoiningProof::try_from(membrane_proof).ok()),
+        author,
+    )
+}
+
+#[hdk_extern]
+pub fn validate_create_entry_member(
+    validate_data: ValidateData,
+) -> ExternResult<ValidateCallbackResult> {
+    let author = validate_data.element.header().author().clone();
+    let joined_at = get_header_time(validate_data.element.header());
+    let validation_package = validate_data.validation_package.clone();
+    Ok(all_valid(vec![
+        // you can only list yourself as a member
+        validate_entry_with(validate_data, |member: Member, author: &AgentPubKey| {
+            Ok(validate_member(&member, author))
+        })?,
+        must_have_joined_with_membrane_proof(validation_package, &author, joined_at)?,
+    ]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn agent(byte: u8) -> AgentPubKey {
+        AgentPubKey::from_raw_36(vec![byte; 36])
+    }
+
+    #[test]
+    fn joining_needs_a_proof_unless_you_are_the_creator() {
+        assert_eq!(
+            check_joining_proof(&agent(1), None, &agent(1)).ok(),
+            Some(ValidateCallbackResult::Valid)
+        );
+        assert_eq!(
+            check_joining_proof(&agent(1), None, &agent(2)).ok(),
+            Some(ValidateCallbackResult::Invalid(
+                "joining this project needs a membrane proof".into()
+            ))
+        );
+    }
+
+    #[test]
+    fn who_can_remove_whom() {
+        assert!(can_remove(&Role::Owner, &Role::Admin));
+        assert!(can_remove(&Role::Admin, &Role::Editor));
+        assert!(can_remove(&Role::Admin, &Role::Viewer));
+        assert!(!can_remove(&Role::Admin, &Role::Admin));
+        assert!(!can_remove(&Role::Admin, &Role::Owner));
+        assert!(!can_remove(&Role::Editor, &Role::Viewer));
+        assert!(!can_remove(&Role::Viewer, &Role::Viewer));
+    }
+}
@@ -4,5 +4,9 @@ pub mod goal;
 pub mod goal_comment;
 pub mod goal_member;
 pub mod goal_vote;
+pub mod invitation;
 pub mod member;
 pub mod project_meta;
+pub mod role;
+pub mod trash;
+pub mod validate;
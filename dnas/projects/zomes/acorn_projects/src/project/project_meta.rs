@@ -1,15 +1,33 @@
+use super::{
+    goal_vote::PriorityWeights,
+    member::project_properties,
+    role::{must_have_role, Role},
+    validate::{validate_project_meta, validate_project_meta_update},
+};
 use crate::{get_peers_content, SignalType};
-use dna_help::{crud, WrappedAgentPubKey};
+use dna_help::{
+    crud,
+    validation::{all_valid, entry_from_element, validate_entry_with, validate_update_with},
+    AcornError, WrappedAgentPubKey, WrappedHeaderHash,
+};
 use hdk3::prelude::*;
+use std::cmp::Ordering;
 
-#[hdk_entry(id = "project_meta")]
+#[hdk_entry(id = "project_meta", required_validation_type = "full")]
 #[derive(Debug, Clone, PartialEq)]
 pub struct ProjectMeta {
     pub creator_address: WrappedAgentPubKey,
     pub created_at: f64,
     pub name: String,
     pub image: Option<String>,
-    pub passphrase: String,
+    // when on, a goal can have at most one parent,
+    // which is what the tree layout assumes
+    #[serde(default)]
+    pub tree_mode: bool,
+    // how goals are scored by `fetch_goal_priorities`,
+    // which uses the default weights when this is None
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub priority_weights: Option<PriorityWeights>,
 }
 
 fn convert_to_receiver_signal(signal: ProjectMetaSignal) -> SignalType {
@@ -21,16 +39,243 @@ crud!(
     project_meta,
     "project_meta",
     get_peers_content,
-    convert_to_receiver_signal
+    convert_to_receiver_signal,
+    check_create: check_new_project_meta,
+    check_change: check_project_meta_change
 );
 
+// There should only ever be one project meta, made by the creator named
+// in the DNA properties when the project is. Validation refuses one made
+// by anyone else, and a second one from the creator, by looking back
+// through their source chain. Projects without a creator in their
+// properties are open to anyone, so there two members can each make one.
+// Every peer settles on the same one: one made by the creator if there is
+// one, then the lowest header address. Header times are left out, as the
+// author sets them.
+pub fn rank_project_metas(a: (bool, &HeaderHash), b: (bool, &HeaderHash)) -> Ordering {
+    b.0.cmp(&a.0)
+        .then_with(|| a.1.to_string().cmp(&b.1.to_string()))
+}
+
+// every live project meta, in rank order
+fn fetch_ranked_project_metas(get_options: GetOptions) -> ExternResult<Vec<ProjectMetaWireEntry>> {
+    let creator = project_properties()?.creator;
+    let mut ranked = Vec::new();
+    for wire_entry in inner_fetch_project_metas(get_options)?.0 {
+        if let Some(element) = get(wire_entry.address.0.clone(), GetOptions::content())? {
+            let by_creator = creator
+                .as_ref()
+                .map_or(false, |creator| &creator.0 == element.header().author());
+            ranked.push((by_creator, wire_entry));
+        }
+    }
+    ranked.sort_by(|a, b| rank_project_metas((a.0, &a.1.address.0), (b.0, &b.1.address.0)));
+    Ok(ranked
+        .into_iter()
+        .map(|(_, wire_entry)| wire_entry)
+        .collect())
+}
+
+fn check_new_project_meta(_: &ProjectMeta) -> ExternResult<()> {
+    if inner_fetch_project_metas(GetOptions::latest())?
+        .0
+        .is_empty()
+    {
+        Ok(())
+    } else {
+        Err(AcornError::ValidationFailed("a project meta already exists".into()).into())
+    }
+}
+
+fn check_project_meta_change(_: &WrappedHeaderHash) -> ExternResult<()> {
+    must_have_role(Role::Admin)?;
+    Ok(())
+}
+
+// updates have to be made against the original header, and not against
+// an earlier update, so that there is a single line of edits to follow
+fn must_update_original_header(element: &Element) -> ExternResult<ValidateCallbackResult> {
+    let original_header_address = match element.header() {
+        Header::Update(update) => update.original_header_address.clone(),
+        _ => {
+            return Ok(ValidateCallbackResult::Invalid(
+                "expected an update header".into(),
+            ))
+        }
+    };
+    match get(original_header_address.clone(), GetOptions::content())? {
+        Some(original) => match original.header() {
+            Header::Create(_) => Ok(ValidateCallbackResult::Valid),
+            _ => Ok(ValidateCallbackResult::Invalid(
+                "a project meta can only be updated at its original header".into(),
+            )),
+        },
+        None => Ok(ValidateCallbackResult::UnresolvedDependencies(vec![
+            original_header_address.into(),
+        ])),
+    }
+}
+
+// Refuses a project meta from an agent whose source chain already holds
+// one which hasn't been deleted. Needs the author's whole chain.
+fn must_be_first_project_meta(
+    element: &Element,
+    validation_package: Option<ValidationPackage>,
+) -> ValidateCallbackResult {
+    let elements = match validation_package {
+        Some(validation_package) => validation_package.0,
+        None => {
+            return ValidateCallbackResult::Invalid(
+                "the author's source chain is needed to check for other project metas".into(),
+            )
+        }
+    };
+    let header_seq = element.header().header_seq();
+    let earlier = elements
+        .iter()
+        .filter(|other| other.header().header_seq() < header_seq);
+    let mut project_metas: Vec<HeaderHash> = Vec::new();
+    for other in earlier {
+        match other.header() {
+            Header::Create(_) => {
+                if let Ok(Some(_)) = entry_from_element::<ProjectMeta>(other) {
+                    project_metas.push(other.header_address().clone());
+                }
+            }
+            Header::Delete(delete) => {
+                project_metas.retain(|project_meta| project_meta != &delete.deletes_address);
+            }
+            _ => {}
+        }
+    }
+    if project_metas.is_empty() {
+        ValidateCallbackResult::Valid
+    } else {
+        ValidateCallbackResult::Invalid("a project can only have one project meta".into())
+    }
+}
+
+#[hdk_extern]
+pub fn validate_create_entry_project_meta(
+    validate_data: ValidateData,
+) -> ExternResult<ValidateCallbackResult> {
+    let creator = project_properties()?.creator;
+    let element = validate_data.element.clone();
+    let validation_package = validate_data.validation_package.clone();
+    Ok(all_valid(vec![
+        validate_entry_with(
+            validate_data,
+            |project_meta: ProjectMeta, author: &AgentPubKey| {
+                Ok(validate_project_meta(
+                    &project_meta,
+                    author,
+                    creator.as_ref().map(|creator| &creator.0),
+                ))
+            },
+        )?,
+        must_be_first_project_meta(&element, validation_package),
+    ]))
+}
+
+#[hdk_extern]
+pub fn validate_update_entry_project_meta(
+    validate_data: ValidateData,
+) -> ExternResult<ValidateCallbackResult> {
+    let element = validate_data.element.clone();
+    Ok(all_valid(vec![
+        validate_update_with(
+            validate_data,
+            |project_meta: ProjectMeta, original: ProjectMeta, _: &AgentPubKey| {
+                Ok(validate_project_meta_update(&project_meta, &original))
+            },
+        )?,
+        must_update_original_header(&element)?,
+    ]))
+}
+
 // READ
 #[hdk_extern]
 pub fn fetch_project_meta(_: ()) -> ExternResult<ProjectMetaWireEntry> {
-    match inner_fetch_project_metas(GetOptions::latest())?.0.first() {
-        Some(wire_entry) => Ok(wire_entry.to_owned()),
-        None => Err(HdkError::Wasm(WasmError::Zome(
-            "no project meta exists".into(),
-        ))),
+    match fetch_ranked_project_metas(GetOptions::latest())?
+        .into_iter()
+        .next()
+    {
+        Some(wire_entry) => Ok(wire_entry),
+        None => Err(AcornError::NotFound("no project meta exists".into()).into()),
+    }
+}
+
+// The agent who created the project, as named in the DNA properties. Projects
+// made before that fall back to whoever made the project meta that ranks first.
+pub fn fetch_project_creator() -> ExternResult<Option<AgentPubKey>> {
+    if let Some(creator) = project_properties()?.creator {
+        return Ok(Some(creator.0));
+    }
+    Ok(fetch_ranked_project_metas(GetOptions::content())?
+        .into_iter()
+        .next()
+        .map(|wire_entry| wire_entry.entry.creator_address.0))
+}
+
+// the address of the project meta which makes the agent the creator,
+// for projects without a creator in their DNA properties
+pub fn fetch_project_meta_of(agent: &AgentPubKey) -> ExternResult<Option<WrappedHeaderHash>> {
+    Ok(fetch_ranked_project_metas(GetOptions::content())?
+        .into_iter()
+        .next()
+        .filter(|wire_entry| &wire_entry.entry.creator_address.0 == agent)
+        .map(|wire_entry| wire_entry.address))
+}
+
+// whether the project is in tree mode, which is off
+// until a project meta exists which turns it on
+pub fn is_tree_mode() -> ExternResult<bool> {
+    Ok(fetch_ranked_project_metas(GetOptions::latest())?
+        .first()
+        .map_or(false, |wire_entry| wire_entry.entry.tree_mode))
+}
+
+// the weights goals are scored with, so that every member ranks them the same
+pub fn fetch_priority_weights() -> ExternResult<PriorityWeights> {
+    Ok(fetch_ranked_project_metas(GetOptions::latest())?
+        .into_iter()
+        .next()
+        .and_then(|wire_entry| wire_entry.entry.priority_weights)
+        .unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header(byte: u8) -> HeaderHash {
+        HeaderHash::from_raw_36(vec![byte; 36])
+    }
+
+    #[test]
+    fn project_meta_by_the_creator_ranks_first() {
+        assert_eq!(
+            rank_project_metas((true, &header(2)), (false, &header(1))),
+            Ordering::Less
+        );
+        assert_eq!(
+            rank_project_metas((false, &header(1)), (true, &header(2))),
+            Ordering::Greater
+        );
+    }
+
+    #[test]
+    fn other_project_metas_rank_by_address() {
+        let (a, b) = (header(1), header(2));
+        let expected = a.to_string().cmp(&b.to_string());
+        assert_eq!(rank_project_metas((true, &a), (true, &b)), expected);
+        assert_eq!(
+            rank_project_metas((false, &b), (false, &a)),
+            expected.reverse()
+        );
+        assert_eq!(
+            rank_project_metas((false, &a), (false, &a)),
+            Ordering::Equal
+        );
     }
 }
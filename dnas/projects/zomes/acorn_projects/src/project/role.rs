@@ -0,0 +1,566 @@
+use super::{
+    invitation::{
+        fetch_redeemed_roles, fetch_redemptions_of, invitation_role, InvitationRedemption,
+    },
+    member::{project_properties, Member, MEMBER_PATH},
+    project_meta::{fetch_project_creator, fetch_project_meta_of, ProjectMeta},
+};
+use crate::{get_peers_content, SignalType};
+use dna_help::{
+    fetch_links, signal_peers,
+    validation::{
+        all_valid, entry_from_app_entry, entry_from_element, must_be_author, validate_entry_with,
+    },
+    AcornError, ActionType, WrappedAgentPubKey, WrappedHeaderHash,
+};
+use hdk3::prelude::*;
+use std::fmt;
+
+// the tag of the links from a Member to their RoleAssignments
+pub const ROLE_LINK_TAG: &str = "role";
+
+// From least to most trusted, so that roles can be compared with < and >.
+// Members who were never assigned a role, or invited with one, are Editors,
+// which is what everyone was before roles existed.
+// The creator of the project is its Owner.
+#[derive(Serialize, Deserialize, Debug, SerializedBytes, Clone, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(try_from = "UIEnum")]
+#[serde(into = "UIEnum")]
+pub enum Role {
+    Viewer,
+    Editor,
+    Admin,
+    Owner,
+}
+
+#[derive(Debug, Serialize, Deserialize, SerializedBytes, Clone, PartialEq)]
+pub struct UIEnum(String);
+
+impl TryFrom<UIEnum> for Role {
+    type Error = String;
+    fn try_from(ui_enum: UIEnum) -> Result<Self, Self::Error> {
+        match ui_enum.0.as_str() {
+            "Owner" => Ok(Self::Owner),
+            "Admin" => Ok(Self::Admin),
+            "Editor" => Ok(Self::Editor),
+            "Viewer" => Ok(Self::Viewer),
+            other => Err(format!("unknown role {:?}", other)),
+        }
+    }
+}
+impl From<Role> for UIEnum {
+    fn from(role: Role) -> Self {
+        Self(role.to_string())
+    }
+}
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+// A role given to a member, linked from their Member entry. Nothing about
+// it can be checked against the links on the DHT while validating, as those
+// differ from peer to peer, so it names the headers it depends on instead.
+#[hdk_entry(id = "role_assignment")]
+#[derive(Debug, Clone, PartialEq)]
+pub struct RoleAssignment {
+    pub agent_address: WrappedAgentPubKey,
+    pub role: Role,
+    pub assigned_by: WrappedAgentPubKey,
+    // what gives `assigned_by` their role: a role assignment or invitation
+    // redemption of theirs, or, in projects without a creator in their
+    // DNA properties, the project meta they made. None for the creator.
+    pub authority: Option<WrappedHeaderHash>,
+    // the role assignments and invitation redemptions of the
+    // agent which this takes the place of
+    pub replaces: Vec<WrappedHeaderHash>,
+    pub unix_timestamp: f64,
+}
+
+#[derive(Serialize, Deserialize, Debug, SerializedBytes, Clone, PartialEq)]
+pub struct RoleAssignmentWireEntry {
+    pub entry: RoleAssignment,
+    pub address: WrappedHeaderHash,
+}
+
+#[derive(Serialize, Deserialize, Debug, SerializedBytes, Clone, PartialEq)]
+pub struct AssignRoleInput {
+    agent_address: WrappedAgentPubKey,
+    role: Role,
+}
+
+#[derive(Serialize, Deserialize, Debug, SerializedBytes, Clone, PartialEq)]
+pub struct MemberRole {
+    agent_address: WrappedAgentPubKey,
+    role: Role,
+}
+
+#[derive(Serialize, Deserialize, Debug, SerializedBytes, Clone, PartialEq)]
+pub struct VecMemberRole(Vec<MemberRole>);
+
+// custom signal type
+#[derive(Debug, Serialize, Deserialize, SerializedBytes)]
+pub struct RoleSignal {
+    entry_type: String,
+    action: ActionType,
+    data: MemberRole,
+}
+
+// Owners can hand out any role. Admins can manage everyone below them,
+// but can't make more Admins or Owners. Nobody else can assign roles.
+pub fn can_assign(assigner: &Role, role: &Role) -> bool {
+    match assigner {
+        Role::Owner => true,
+        Role::Admin => role < &Role::Admin,
+        _ => false,
+    }
+}
+
+pub fn member_entry_hash(agent_address: &AgentPubKey) -> ExternResult<EntryHash> {
+    Ok(hash_entry(&Member {
+        address: WrappedAgentPubKey(agent_address.clone()),
+    })?)
+}
+
+// the role assignments for an agent which are linked from their Member entry
+fn fetch_role_assignments(
+    agent_address: &AgentPubKey,
+) -> ExternResult<Vec<RoleAssignmentWireEntry>> {
+    let links = get_links(
+        member_entry_hash(agent_address)?,
+        Some(LinkTag::new(ROLE_LINK_TAG)),
+    )?
+    .into_inner();
+    let mut assignments = Vec::new();
+    for link in links {
+        if let Some(Details::Entry(details)) = get_details(link.target, GetOptions::content())? {
+            let role_assignment = match entry_from_app_entry::<RoleAssignment>(&details.entry) {
+                // a link from one Member to the assignment of another doesn't count
+                Some(role_assignment) if &role_assignment.agent_address.0 == agent_address => {
+                    role_assignment
+                }
+                _ => continue,
+            };
+            for create in details.headers.iter() {
+                assignments.push(RoleAssignmentWireEntry {
+                    entry: role_assignment.clone(),
+                    address: WrappedHeaderHash(create.header_address().clone()),
+                });
+            }
+        }
+    }
+    Ok(assignments)
+}
+
+// The roles an agent has, each with the header that gives it: their role
+// assignments and invitation redemptions which no role assignment has taken
+// the place of. There is usually just one, but assignments made at the same
+// time can each replace the same one.
+fn fetch_current_roles(
+    agent_address: &AgentPubKey,
+) -> ExternResult<Vec<(WrappedHeaderHash, Role)>> {
+    let assignments = fetch_role_assignments(agent_address)?;
+    let replaced: Vec<WrappedHeaderHash> = assignments
+        .iter()
+        .flat_map(|assignment| assignment.entry.replaces.clone())
+        .collect();
+    let mut roles: Vec<(WrappedHeaderHash, Role)> = assignments
+        .into_iter()
+        .map(|assignment| (assignment.address, assignment.entry.role))
+        .collect();
+    roles.extend(fetch_redeemed_roles(agent_address)?);
+    roles.retain(|(address, _)| !replaced.contains(address));
+    Ok(roles)
+}
+
+// Of competing roles, the most trusted one counts, so that an assignment
+// that leaves out a role it should have replaced can't take it away.
+// This reads links, which differ from peer to peer, so it is only used
+// before making changes, and never while validating. Validation can only
+// check a role through a header the entry names, as role assignments and
+// invitations do. Updates and deletes of goals, comments, entry points, the
+// project meta, invitations and members have nowhere to name one, so their
+// roles are only checked here, by the zome functions that make them.
+pub fn role_of(agent_address: &AgentPubKey) -> ExternResult<Role> {
+    if fetch_project_creator()?.as_ref() == Some(agent_address) {
+        return Ok(Role::Owner);
+    }
+    Ok(fetch_current_roles(agent_address)?
+        .into_iter()
+        .map(|(_, role)| role)
+        .max()
+        .unwrap_or(Role::Editor))
+}
+
+// my role, as long as it is at least the given one
+pub fn must_have_role(at_least: Role) -> ExternResult<Role> {
+    let role = role_of(&agent_info()?.agent_initial_pubkey)?;
+    if role >= at_least {
+        Ok(role)
+    } else {
+        Err(AcornError::Unauthorized(format!(
+            "this needs the {} role, and you are a {}",
+            at_least, role
+        ))
+        .into())
+    }
+}
+
+fn send_role_signal(agent_address: WrappedAgentPubKey, role: Role, action: ActionType) {
+    let signal = SignalType::Role(RoleSignal {
+        entry_type: "role".to_string(),
+        action,
+        data: MemberRole {
+            agent_address,
+            role,
+        },
+    });
+    let _ = debug!(format!("ROLE ACTION SIGNAL PEERS {:?}", signal));
+    let _ = signal_peers(&signal, get_peers_content);
+}
+
+fn unauthorized(role: &Role) -> HdkError {
+    AcornError::Unauthorized(format!("a {} can't change this role", role)).into()
+}
+
+// what gives me my role, to name as the authority of the
+// role assignments and invitations I make
+pub fn fetch_my_authority(me: &AgentPubKey) -> ExternResult<Option<WrappedHeaderHash>> {
+    match project_properties()?.creator {
+        Some(creator) if &creator.0 == me => return Ok(None),
+        Some(_) => (),
+        None => {
+            if let Some(address) = fetch_project_meta_of(me)? {
+                return Ok(Some(address));
+            }
+        }
+    }
+    let mut roles = fetch_current_roles(me)?;
+    roles.sort_by(|a, b| a.1.cmp(&b.1));
+    Ok(roles.pop().map(|(address, _)| address))
+}
+
+// Give a member a role, replacing whatever roles they had.
+fn inner_assign_role(
+    agent_address: WrappedAgentPubKey,
+    role: Role,
+) -> ExternResult<RoleAssignmentWireEntry> {
+    let me = agent_info()?.agent_initial_pubkey;
+    let my_role = role_of(&me)?;
+    let current_roles = fetch_current_roles(&agent_address.0)?;
+    if fetch_project_creator()?.as_ref() == Some(&agent_address.0)
+        || !can_assign(&my_role, &role)
+        || current_roles
+            .iter()
+            .any(|(_, current_role)| !can_assign(&my_role, current_role))
+    {
+        return Err(unauthorized(&my_role));
+    }
+    let role_assignment = RoleAssignment {
+        agent_address: agent_address.clone(),
+        role,
+        assigned_by: WrappedAgentPubKey(me.clone()),
+        authority: fetch_my_authority(&me)?,
+        replaces: current_roles
+            .into_iter()
+            .map(|(address, _)| address)
+            .collect(),
+        unix_timestamp: sys_time()?.as_secs_f64(),
+    };
+    let address = create_entry(&role_assignment)?;
+    create_link(
+        member_entry_hash(&agent_address.0)?,
+        hash_entry(&role_assignment)?,
+        LinkTag::new(ROLE_LINK_TAG),
+    )?;
+    Ok(RoleAssignmentWireEntry {
+        entry: role_assignment,
+        address: WrappedHeaderHash(address),
+    })
+}
+
+#[hdk_extern]
+pub fn assign_role(input: AssignRoleInput) -> ExternResult<RoleAssignmentWireEntry> {
+    let wire_entry = inner_assign_role(input.agent_address.clone(), input.role.clone())?;
+    send_role_signal(input.agent_address, input.role, ActionType::Create);
+    Ok(wire_entry)
+}
+
+// Take away the roles a member was given, which makes them an Editor again.
+// Role assignments are never deleted, so this assigns them Editor instead.
+#[hdk_extern]
+pub fn revoke_role(agent_address: WrappedAgentPubKey) -> ExternResult<MemberRole> {
+    inner_assign_role(agent_address.clone(), Role::Editor)?;
+    send_role_signal(agent_address.clone(), Role::Editor, ActionType::Delete);
+    Ok(MemberRole {
+        agent_address,
+        role: Role::Editor,
+    })
+}
+
+// The members whose role changed after `since`, each as the signal that
+// gives their role now, at the time of the latest change.
+pub fn fetch_role_changes_since(since: f64) -> ExternResult<Vec<(f64, RoleSignal)>> {
+    let path_hash = Path::from(MEMBER_PATH).hash()?;
+    let members = fetch_links::<Member, Member>(path_hash, GetOptions::content())?;
+    let mut changes = Vec::new();
+    for member in members {
+        let assigned_at = fetch_role_assignments(&member.address.0)?
+            .into_iter()
+            .map(|assignment| assignment.entry.unix_timestamp);
+        let redeemed_at = fetch_redemptions_of(&member.address.0)?
+            .into_iter()
+            .map(|redemption| redemption.entry.redeemed_at);
+        let changed_at = assigned_at
+            .chain(redeemed_at)
+            .filter(|time| time > &since)
+            .fold(None, |latest: Option<f64>, time| match latest {
+                Some(latest) if latest >= time => Some(latest),
+                _ => Some(time),
+            });
+        if let Some(changed_at) = changed_at {
+            changes.push((
+                changed_at,
+                RoleSignal {
+                    entry_type: "role".to_string(),
+                    action: ActionType::Create,
+                    data: MemberRole {
+                        role: role_of(&member.address.0)?,
+                        agent_address: member.address,
+                    },
+                },
+            ));
+        }
+    }
+    Ok(changes)
+}
+
+#[hdk_extern]
+pub fn fetch_member_roles(_: ()) -> ExternResult<VecMemberRole> {
+    let path_hash = Path::from(MEMBER_PATH).hash()?;
+    let members = fetch_links::<Member, Member>(path_hash, GetOptions::latest())?;
+    let mut member_roles = Vec::new();
+    for member in members {
+        member_roles.push(MemberRole {
+            role: role_of(&member.address.0)?,
+            agent_address: member.address,
+        });
+    }
+    Ok(VecMemberRole(member_roles))
+}
+
+// An assigner can only replace roles they could have assigned themselves.
+pub fn check_role_assignment(
+    assigner_role: &Role,
+    role: &Role,
+    replaced_roles: &[Role],
+) -> ValidateCallbackResult {
+    if !can_assign(assigner_role, role) {
+        return ValidateCallbackResult::Invalid(format!(
+            "a {} can't assign the {} role",
+            assigner_role, role
+        ));
+    }
+    match replaced_roles
+        .iter()
+        .find(|replaced_role| !can_assign(assigner_role, replaced_role))
+    {
+        Some(replaced_role) => ValidateCallbackResult::Invalid(format!(
+            "a {} can't change the role of a {}",
+            assigner_role, replaced_role
+        )),
+        None => ValidateCallbackResult::Valid,
+    }
+}
+
+// The role that the header at `address` gives `agent_address`. Headers don't
+// change once they're on the DHT, so every validator comes to the same answer.
+fn role_given_by(
+    address: &WrappedHeaderHash,
+    agent_address: &AgentPubKey,
+) -> ExternResult<Result<Role, ValidateCallbackResult>> {
+    let element = match get(address.0.clone(), GetOptions::content())? {
+        Some(element) => element,
+        None => {
+            return Ok(Err(ValidateCallbackResult::UnresolvedDependencies(vec![
+                address.0.clone().into(),
+            ])))
+        }
+    };
+    let given_to_someone_else = || {
+        Ok(Err(ValidateCallbackResult::Invalid(
+            "a role can only be based on one given to the same agent".into(),
+        )))
+    };
+    if let Some(role_assignment) = entry_from_element::<RoleAssignment>(&element)
+        .ok()
+        .flatten()
+    {
+        if &role_assignment.agent_address.0 != agent_address {
+            return given_to_someone_else();
+        }
+        return Ok(Ok(role_assignment.role));
+    }
+    if let Some(redemption) = entry_from_element::<InvitationRedemption>(&element)
+        .ok()
+        .flatten()
+    {
+        if &redemption.agent_address.0 != agent_address {
+            return given_to_someone_else();
+        }
+        return match invitation_role(&redemption.invitation_address)? {
+            Some(role) => Ok(Ok(role)),
+            None => Ok(Err(ValidateCallbackResult::UnresolvedDependencies(vec![
+                redemption.invitation_address.0.into(),
+            ]))),
+        };
+    }
+    if entry_from_element::<ProjectMeta>(&element)
+        .ok()
+        .flatten()
+        .is_some()
+        && element.header().author() == agent_address
+        && project_properties()?.creator.is_none()
+    {
+        return Ok(Ok(Role::Owner));
+    }
+    Ok(Err(ValidateCallbackResult::Invalid(
+        "a role can only be based on a role assignment or invitation redemption".into(),
+    )))
+}
+
+// The role of the author of an entry which names the header that gives it,
+// or, when it names none, the role of the creator in the DNA properties.
+// That header can't show that the role was replaced later, which is only
+// known from the links, and checked with role_of before the entry is made.
+pub fn role_from_authority(
+    authority: &Option<WrappedHeaderHash>,
+    author: &AgentPubKey,
+) -> ExternResult<Result<Role, ValidateCallbackResult>> {
+    match (authority, project_properties()?.creator) {
+        (None, Some(creator)) if &creator.0 == author => Ok(Ok(Role::Owner)),
+        (None, _) => Ok(Err(ValidateCallbackResult::Invalid(
+            "this needs an authority for the role of the author".into(),
+        ))),
+        (Some(authority), _) => role_given_by(authority, author),
+    }
+}
+
+#[hdk_extern]
+pub fn validate_create_entry_role_assignment(
+    validate_data: ValidateData,
+) -> ExternResult<ValidateCallbackResult> {
+    validate_entry_with(
+        validate_data,
+        |role_assignment: RoleAssignment, author: &AgentPubKey| {
+            if project_properties()?.creator.as_ref() == Some(&role_assignment.agent_address) {
+                return Ok(ValidateCallbackResult::Invalid(
+                    "the creator of the project is always its Owner".into(),
+                ));
+            }
+            let author_role = match role_from_authority(&role_assignment.authority, author)? {
+                Ok(role) => role,
+                Err(result) => return Ok(result),
+            };
+            let mut replaced_roles = Vec::new();
+            for replaced in role_assignment.replaces.iter() {
+                match role_given_by(replaced, &role_assignment.agent_address.0)? {
+                    Ok(role) => replaced_roles.push(role),
+                    Err(result) => return Ok(result),
+                }
+            }
+            Ok(all_valid(vec![
+                must_be_author(author, &role_assignment.assigned_by, "assigned_by"),
+                check_role_assignment(&author_role, &role_assignment.role, &replaced_roles),
+            ]))
+        },
+    )
+}
+
+#[hdk_extern]
+pub fn validate_delete_entry_role_assignment(
+    _: ValidateData,
+) -> ExternResult<ValidateCallbackResult> {
+    Ok(ValidateCallbackResult::Invalid(
+        "role assignments can't be deleted, but can be replaced by assigning another role".into(),
+    ))
+}
+
+// Role links have to come from the Member entry of the agent
+// the role is for, and be made by whoever assigned it.
+pub fn validate_role_link(
+    validate_data: ValidateCreateLinkData,
+) -> ExternResult<ValidateLinkCallbackResult> {
+    let role_assignment = match entry_from_app_entry::<RoleAssignment>(&validate_data.target) {
+        Some(role_assignment) => role_assignment,
+        None => {
+            return Ok(ValidateLinkCallbackResult::Invalid(
+                "role links must point at a role assignment".into(),
+            ))
+        }
+    };
+    if hash_entry(&validate_data.base)? != member_entry_hash(&role_assignment.agent_address.0)? {
+        return Ok(ValidateLinkCallbackResult::Invalid(
+            "a role can only be linked from the Member entry of its agent".into(),
+        ));
+    }
+    if validate_data.link_add.author != role_assignment.assigned_by.0 {
+        return Ok(ValidateLinkCallbackResult::Invalid(
+            "a role can only be linked by whoever assigned it".into(),
+        ));
+    }
+    Ok(ValidateLinkCallbackResult::Valid)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn roles_are_ordered_by_trust() {
+        assert!(Role::Viewer < Role::Editor);
+        assert!(Role::Editor < Role::Admin);
+        assert!(Role::Admin < Role::Owner);
+    }
+
+    #[test]
+    fn roles_must_be_known() {
+        assert_eq!(Role::try_from(UIEnum("Admin".into())), Ok(Role::Admin));
+        assert!(Role::try_from(UIEnum("Superuser".into())).is_err());
+    }
+
+    #[test]
+    fn assignments_can_only_replace_lesser_roles() {
+        assert_eq!(
+            check_role_assignment(&Role::Admin, &Role::Viewer, &[Role::Editor]),
+            ValidateCallbackResult::Valid
+        );
+        assert_eq!(
+            check_role_assignment(&Role::Owner, &Role::Viewer, &[Role::Admin]),
+            ValidateCallbackResult::Valid
+        );
+        // an Admin can't demote another Admin
+        assert_ne!(
+            check_role_assignment(&Role::Admin, &Role::Viewer, &[Role::Admin]),
+            ValidateCallbackResult::Valid
+        );
+        assert_ne!(
+            check_role_assignment(&Role::Admin, &Role::Admin, &[]),
+            ValidateCallbackResult::Valid
+        );
+    }
+
+    #[test]
+    fn who_can_assign_what() {
+        for role in vec![Role::Viewer, Role::Editor, Role::Admin, Role::Owner] {
+            assert!(can_assign(&Role::Owner, &role));
+            assert!(!can_assign(&Role::Editor, &role));
+            assert!(!can_assign(&Role::Viewer, &role));
+        }
+        assert!(can_assign(&Role::Admin, &Role::Viewer));
+        assert!(can_assign(&Role::Admin, &Role::Editor));
+        assert!(!can_assign(&Role::Admin, &Role::Admin));
+        assert!(!can_assign(&Role::Admin, &Role::Owner));
+    }
+}
@@ -0,0 +1,369 @@
+use super::{
+    edge::{inner_create_edge, Edge, EdgeWireEntry, EDGE_PATH},
+    entry_point::{inner_create_entry_point, EntryPoint, EntryPointWireEntry, ENTRY_POINT_PATH},
+    goal::{inner_create_goal, ArchiveGoalFullyResponse, Goal, GoalWireEntry, GOAL_PATH},
+    goal_comment::{
+        inner_create_goal_comment, GoalComment, GoalCommentWireEntry, GOAL_COMMENT_PATH,
+    },
+    goal_member::{inner_create_goal_member, GoalMember, GoalMemberWireEntry, GOAL_MEMBER_PATH},
+    goal_vote::{inner_create_goal_vote, GoalVote, GoalVoteWireEntry, GOAL_VOTE_PATH},
+};
+use crate::{get_peers_content, SignalType};
+use dna_help::{
+    changes::{fetch_changes_since, Change},
+    create_index_link, get_header_hash, get_header_time, get_latest_for_entry, index_path,
+    signal_peers,
+    validation::{entry_from_element, must_be_live_entry},
+    AcornError, ActionType, WrappedAgentPubKey, WrappedHeaderHash,
+};
+use hdk3::prelude::*;
+
+// how long an archived goal stays listed in the trash
+const TRASH_DAYS: f64 = 30.0;
+const SECONDS_PER_DAY: f64 = 86_400.0;
+// the tag of the link from the index of an archived goal
+// to the goal it was restored as
+const RESTORED_AS: &str = "restored_as";
+// why an archived record can be left archived by restore_goal_fully
+const ON_ANOTHER_GOAL: &str = "it belongs to another goal";
+const BY_SOMEONE_ELSE: &str = "it was written by someone else";
+
+#[derive(Serialize, Deserialize, Debug, SerializedBytes, Clone, PartialEq)]
+pub struct RestoreGoalFullyResponse {
+    goal: GoalWireEntry,
+    restored_edges: Vec<EdgeWireEntry>,
+    restored_goal_members: Vec<GoalMemberWireEntry>,
+    restored_goal_votes: Vec<GoalVoteWireEntry>,
+    restored_goal_comments: Vec<GoalCommentWireEntry>,
+    restored_entry_points: Vec<EntryPointWireEntry>,
+    // archived records which were left archived, and why
+    not_restored: Vec<NotRestored>,
+}
+
+#[derive(Serialize, Deserialize, Debug, SerializedBytes, Clone, PartialEq)]
+pub struct NotRestored {
+    address: WrappedHeaderHash,
+    reason: String,
+}
+
+// custom signal type
+#[derive(Debug, Serialize, Deserialize, SerializedBytes)]
+pub struct RestoreGoalFullySignal {
+    entry_type: String,
+    action: ActionType,
+    data: RestoreGoalFullyResponse,
+}
+
+#[derive(Serialize, Deserialize, Debug, SerializedBytes, Clone, PartialEq)]
+pub struct TrashedGoal {
+    goal: Goal,
+    archived_at: f64,
+    // what restore_goal_fully would bring back, when given archive.address
+    archive: ArchiveGoalFullyResponse,
+}
+
+#[derive(Serialize, Deserialize, Debug, SerializedBytes, Clone, PartialEq)]
+pub struct VecTrashedGoal(Vec<TrashedGoal>);
+
+// The latest version of the entry created at `address`, even if it has
+// since been deleted. Every update in this zome is made against the
+// original header, so they are all found one step away from it.
+fn get_latest_for_header<T: TryFrom<SerializedBytes, Error = SerializedBytesError>>(
+    address: &WrappedHeaderHash,
+) -> ExternResult<Option<T>> {
+    match get_details(address.0.clone(), GetOptions::content())? {
+        Some(Details::Element(details)) => {
+            let mut updates = details.updates;
+            updates.sort_by(|a, b| {
+                get_header_time(a.header())
+                    .partial_cmp(&get_header_time(b.header()))
+                    .unwrap_or(std::cmp::Ordering::Equal)
+            });
+            match updates.pop() {
+                Some(latest) => {
+                    match get_details(get_header_hash(latest), GetOptions::content())? {
+                        Some(Details::Element(details)) => {
+                            entry_from_element::<T>(&details.element)
+                        }
+                        _ => Ok(None),
+                    }
+                }
+                None => entry_from_element::<T>(&details.element),
+            }
+        }
+        _ => Ok(None),
+    }
+}
+
+fn restored_as_links(address: &WrappedHeaderHash) -> ExternResult<Vec<link::Link>> {
+    let path_hash = index_path("goal", address).hash()?;
+    Ok(get_links(path_hash, Some(LinkTag::new(RESTORED_AS)))?.into_inner())
+}
+
+// Where a goal lives now: at its own address, or if it was archived and
+// then restored, at the address it was restored as. None if it's gone.
+fn current_goal_address(address: &WrappedHeaderHash) -> ExternResult<Option<WrappedHeaderHash>> {
+    if must_be_live_entry::<Goal>(&address.0, "goal")? == ValidateCallbackResult::Valid {
+        return Ok(Some(address.clone()));
+    }
+    for link in restored_as_links(address)? {
+        if let Some((_, restored_address, _)) =
+            get_latest_for_entry::<Goal>(link.target, GetOptions::content())?
+        {
+            return current_goal_address(&WrappedHeaderHash(restored_address));
+        }
+    }
+    Ok(None)
+}
+
+// the records of one type in the index of a goal which were deleted
+// at or after the given time, which are the ones archive_goal_fully took with it
+fn archived_for_goal(
+    address: &WrappedHeaderHash,
+    link_tag: &str,
+    since: f64,
+) -> ExternResult<Vec<WrappedHeaderHash>> {
+    let path_hash = index_path("goal", address).hash()?;
+    let mut archived: Vec<WrappedHeaderHash> = Vec::new();
+    for link in get_links(path_hash, Some(LinkTag::new(link_tag)))?.into_inner() {
+        if let Some(Details::Entry(details)) = get_details(link.target, GetOptions::content())? {
+            for delete in details.deletes.iter() {
+                if let Header::Delete(delete_header) = delete.header() {
+                    let deleted = WrappedHeaderHash(delete_header.deletes_address.clone());
+                    if get_header_time(delete.header()) >= since && !archived.contains(&deleted) {
+                        archived.push(deleted);
+                    }
+                }
+            }
+        }
+    }
+    Ok(archived)
+}
+
+// everything archived along with a goal which was archived at the given time
+fn archived_with_goal(
+    address: WrappedHeaderHash,
+    archived_at: f64,
+) -> ExternResult<ArchiveGoalFullyResponse> {
+    Ok(ArchiveGoalFullyResponse {
+        archived_edges: archived_for_goal(&address, EDGE_PATH, archived_at)?,
+        archived_goal_members: archived_for_goal(&address, GOAL_MEMBER_PATH, archived_at)?,
+        archived_goal_votes: archived_for_goal(&address, GOAL_VOTE_PATH, archived_at)?,
+        archived_goal_comments: archived_for_goal(&address, GOAL_COMMENT_PATH, archived_at)?,
+        archived_entry_points: archived_for_goal(&address, ENTRY_POINT_PATH, archived_at)?,
+        address,
+    })
+}
+
+// when the goal at the address was first archived, if it was
+fn fetch_archived_at(address: &WrappedHeaderHash) -> ExternResult<Option<f64>> {
+    match get_details(address.0.clone(), GetOptions::content())? {
+        Some(Details::Element(details)) => Ok(details
+            .deletes
+            .iter()
+            .map(|delete| get_header_time(delete.header()))
+            .fold(None, |earliest: Option<f64>, time| match earliest {
+                Some(earliest) if earliest <= time => Some(earliest),
+                _ => Some(time),
+            })),
+        _ => Ok(None),
+    }
+}
+
+// Goals archived within the last TRASH_DAYS which haven't been restored
+// yet, newest first, each with everything that was archived along with it.
+#[hdk_extern]
+pub fn fetch_trash(_: ()) -> ExternResult<VecTrashedGoal> {
+    let since = sys_time()?.as_secs_f64() - TRASH_DAYS * SECONDS_PER_DAY;
+    let mut trash: Vec<TrashedGoal> = Vec::new();
+    for (archived_at, change) in fetch_changes_since::<Goal>(GOAL_PATH, since)? {
+        let address = match change {
+            Change::Delete(address) => WrappedHeaderHash(address),
+            _ => continue,
+        };
+        if !restored_as_links(&address)?.is_empty() {
+            continue;
+        }
+        let goal = match get_latest_for_header::<Goal>(&address)? {
+            Some(goal) => goal,
+            None => continue,
+        };
+        trash.push(TrashedGoal {
+            goal,
+            archived_at,
+            archive: archived_with_goal(address, archived_at)?,
+        });
+    }
+    trash.sort_by(|a, b| {
+        b.archived_at
+            .partial_cmp(&a.archived_at)
+            .unwrap_or(std::cmp::Ordering::Equal)
+    });
+    Ok(VecTrashedGoal(trash))
+}
+
+// Recreate each archived record using `restore`, which gives back the
+// reason a record has to stay archived instead. Those are added to `not_restored`.
+fn restore_each<T, W, F>(
+    addresses: Vec<WrappedHeaderHash>,
+    not_restored: &mut Vec<NotRestored>,
+    mut restore: F,
+) -> ExternResult<Vec<W>>
+where
+    T: TryFrom<SerializedBytes, Error = SerializedBytesError>,
+    F: FnMut(T) -> ExternResult<Result<W, String>>,
+{
+    let mut restored = Vec::new();
+    for address in addresses {
+        let result = match get_latest_for_header::<T>(&address)? {
+            Some(entry) => restore(entry)?,
+            None => Err("it can't be found".to_string()),
+        };
+        match result {
+            Ok(wire_entry) => restored.push(wire_entry),
+            Err(reason) => not_restored.push(NotRestored { address, reason }),
+        }
+    }
+    Ok(restored)
+}
+
+// Bring back a goal archived by archive_goal_fully, along with everything
+// archived with it, all pointed at the restored goal. What was archived with
+// it is found again through the goal's index. Records can only be recreated by
+// whoever could have written them, so votes, comments and entry points by
+// other agents stay archived, as do edges to goals which are gone, or which
+// the tree would now refuse. Each of those is listed in `not_restored`.
+#[hdk_extern]
+pub fn restore_goal_fully(address: WrappedHeaderHash) -> ExternResult<RestoreGoalFullyResponse> {
+    if !restored_as_links(&address)?.is_empty() {
+        return Err(AcornError::ValidationFailed("goal was already restored".into()).into());
+    }
+    let mut goal = match get_latest_for_header::<Goal>(&address)? {
+        Some(goal) => goal,
+        None => return Err(AcornError::NotFound("no goal exists".into()).into()),
+    };
+    let archive = match fetch_archived_at(&address)? {
+        Some(archived_at) => archived_with_goal(address.clone(), archived_at)?,
+        None => return Err(AcornError::ValidationFailed("the goal isn't archived".into()).into()),
+    };
+    let me = WrappedAgentPubKey(agent_info()?.agent_initial_pubkey);
+    let now = sys_time()?.as_secs_f64();
+
+    // restoring counts as an edit, which also keeps the restored
+    // goal from having the same entry hash as the archived one
+    goal.user_edit_hash = Some(me.clone());
+    goal.timestamp_updated = Some(now);
+    goal.restored_from = Some(address.clone());
+    let goal = inner_create_goal(goal, false)?;
+    create_index_link("goal", &address, goal.entry_address.0.clone(), RESTORED_AS)?;
+    let old_address = address;
+    let new_address = goal.address.clone();
+    // the reason to leave a record archived, if there is one
+    let check = |goal_address: &WrappedHeaderHash, author: &WrappedAgentPubKey| {
+        if goal_address != &old_address {
+            Some(ON_ANOTHER_GOAL.to_string())
+        } else if author != &me {
+            Some(BY_SOMEONE_ELSE.to_string())
+        } else {
+            None
+        }
+    };
+
+    let mut not_restored = Vec::new();
+    let restored_edges = restore_each(
+        archive.archived_edges,
+        &mut not_restored,
+        |mut edge: Edge| {
+            if edge.parent_address != old_address && edge.child_address != old_address {
+                return Ok(Err(ON_ANOTHER_GOAL.to_string()));
+            }
+            let move_end = |end: WrappedHeaderHash| {
+                if end == old_address {
+                    Ok(Some(new_address.clone()))
+                } else {
+                    current_goal_address(&end)
+                }
+            };
+            match (
+                move_end(edge.parent_address)?,
+                move_end(edge.child_address)?,
+            ) {
+                (Some(parent_address), Some(child_address)) => {
+                    edge.parent_address = parent_address;
+                    edge.child_address = child_address;
+                    edge.randomizer = now;
+                    Ok(inner_create_edge(edge, false).map_err(|e| format!("{:?}", e)))
+                }
+                _ => Ok(Err("the goal at its other end is gone".to_string())),
+            }
+        },
+    )?;
+    let restored_goal_members = restore_each(
+        archive.archived_goal_members,
+        &mut not_restored,
+        |mut goal_member: GoalMember| {
+            // anyone can assign members, so anyone can assign them again
+            if goal_member.goal_address != old_address {
+                return Ok(Err(ON_ANOTHER_GOAL.to_string()));
+            }
+            goal_member.goal_address = new_address.clone();
+            goal_member.user_edit_hash = Some(me.clone());
+            Ok(Ok(inner_create_goal_member(goal_member, false)?))
+        },
+    )?;
+    let restored_goal_votes = restore_each(
+        archive.archived_goal_votes,
+        &mut not_restored,
+        |mut goal_vote: GoalVote| {
+            if let Some(reason) = check(&goal_vote.goal_address, &goal_vote.agent_address) {
+                return Ok(Err(reason));
+            }
+            goal_vote.goal_address = new_address.clone();
+            Ok(Ok(inner_create_goal_vote(goal_vote, false)?))
+        },
+    )?;
+    let restored_goal_comments = restore_each(
+        archive.archived_goal_comments,
+        &mut not_restored,
+        |mut goal_comment: GoalComment| {
+            if let Some(reason) = check(&goal_comment.goal_address, &goal_comment.agent_address) {
+                return Ok(Err(reason));
+            }
+            goal_comment.goal_address = new_address.clone();
+            Ok(Ok(inner_create_goal_comment(goal_comment, false)?))
+        },
+    )?;
+    let restored_entry_points = restore_each(
+        archive.archived_entry_points,
+        &mut not_restored,
+        |mut entry_point: EntryPoint| {
+            if let Some(reason) = check(&entry_point.goal_address, &entry_point.creator_address) {
+                return Ok(Err(reason));
+            }
+            entry_point.goal_address = new_address.clone();
+            Ok(Ok(inner_create_entry_point(entry_point, false)?))
+        },
+    )?;
+
+    let restore_response = RestoreGoalFullyResponse {
+        goal,
+        restored_edges,
+        restored_goal_members,
+        restored_goal_votes,
+        restored_goal_comments,
+        restored_entry_points,
+        not_restored,
+    };
+    let signal = SignalType::RestoreGoalFully(RestoreGoalFullySignal {
+        entry_type: "restore_goal_fully".to_string(),
+        action: ActionType::Create,
+        data: restore_response.clone(),
+    });
+    let _ = debug!(format!(
+        "RESTORE GOAL FULLY ACTION SIGNAL PEERS {:?}",
+        signal
+    ));
+    let _ = signal_peers(&signal, get_peers_content);
+
+    Ok(restore_response)
+}
@@ -0,0 +1,443 @@
+use super::{
+    edge::{Edge, EdgeError},
+    entry_point::EntryPoint,
+    goal::{Goal, Hierarchy, Status},
+    goal_comment::GoalComment,
+    goal_member::GoalMember,
+    goal_vote::{GoalVote, PriorityWeights},
+    member::Member,
+    project_meta::ProjectMeta,
+};
+use dna_help::{
+    validation::{all_valid, must_be_author, must_be_in_range, must_not_be_empty},
+    WrappedAgentPubKey,
+};
+use hdk3::prelude::*;
+
+// The per entry type rules used by the validate_*_entry_* callbacks.
+// These only look at the entry and its author, so they can be unit tested
+// without a conductor. Anything needing the DHT (like checking that
+// an address points at a live Goal) happens in the callbacks themselves.
+
+pub fn validate_goal_create(goal: &Goal, author: &AgentPubKey) -> ValidateCallbackResult {
+    must_be_author(author, &goal.user_hash, "user_hash")
+}
+
+// Someone else's goal can be recreated when restoring it from the trash.
+// It stays theirs, and whoever restored it has to say that they did,
+// the same as for an update.
+pub fn validate_goal_restore(
+    goal: &Goal,
+    restored_from: &Goal,
+    author: &AgentPubKey,
+) -> ValidateCallbackResult {
+    all_valid(vec![
+        must_be_editor(&goal.user_edit_hash, author),
+        if goal.user_hash == restored_from.user_hash {
+            ValidateCallbackResult::Valid
+        } else {
+            ValidateCallbackResult::Invalid(
+                "a restored goal keeps the user_hash of the goal it was restored from".into(),
+            )
+        },
+    ])
+}
+
+// whoever makes a change has to say that they made it
+fn must_be_editor(
+    user_edit_hash: &Option<WrappedAgentPubKey>,
+    author: &AgentPubKey,
+) -> ValidateCallbackResult {
+    match user_edit_hash {
+        Some(user_edit_hash) => must_be_author(author, user_edit_hash, "user_edit_hash"),
+        None => ValidateCallbackResult::Invalid("user_edit_hash must be set".into()),
+    }
+}
+
+// anyone can edit a goal, but it stays with whoever created it
+pub fn validate_goal_update(
+    goal: &Goal,
+    original: &Goal,
+    author: &AgentPubKey,
+) -> ValidateCallbackResult {
+    all_valid(vec![
+        must_be_editor(&goal.user_edit_hash, author),
+        if goal.user_hash == original.user_hash {
+            ValidateCallbackResult::Valid
+        } else {
+            ValidateCallbackResult::Invalid("user_hash can't be changed".into())
+        },
+    ])
+}
+
+pub fn validate_edge(edge: &Edge) -> ValidateCallbackResult {
+    if edge.parent_address == edge.child_address {
+        ValidateCallbackResult::Invalid(EdgeError::SelfLoop.to_string())
+    } else {
+        ValidateCallbackResult::Valid
+    }
+}
+
+pub fn validate_goal_vote(goal_vote: &GoalVote, author: &AgentPubKey) -> ValidateCallbackResult {
+    all_valid(vec![
+        must_be_author(author, &goal_vote.agent_address, "agent_address"),
+        must_be_in_range(goal_vote.urgency, 0.0, 1.0, "urgency"),
+        must_be_in_range(goal_vote.importance, 0.0, 1.0, "importance"),
+        must_be_in_range(goal_vote.impact, 0.0, 1.0, "impact"),
+        must_be_in_range(goal_vote.effort, 0.0, 1.0, "effort"),
+    ])
+}
+
+pub fn validate_goal_member(
+    goal_member: &GoalMember,
+    author: &AgentPubKey,
+) -> ValidateCallbackResult {
+    // agent_address is the agent being assigned, which can be anyone,
+    // but whoever did the assigning has to be honest about it
+    must_be_editor(&goal_member.user_edit_hash, author)
+}
+
+pub fn validate_goal_comment(
+    goal_comment: &GoalComment,
+    author: &AgentPubKey,
+) -> ValidateCallbackResult {
+    all_valid(vec![
+        must_be_author(author, &goal_comment.agent_address, "agent_address"),
+        must_not_be_empty(&goal_comment.content, "content"),
+    ])
+}
+
+pub fn validate_entry_point(
+    entry_point: &EntryPoint,
+    author: &AgentPubKey,
+) -> ValidateCallbackResult {
+    must_be_author(author, &entry_point.creator_address, "creator_address")
+}
+
+// Only the creator named in the DNA properties can make the project meta.
+// Projects without one in their properties can't tell who that is.
+pub fn validate_project_meta(
+    project_meta: &ProjectMeta,
+    author: &AgentPubKey,
+    creator: Option<&AgentPubKey>,
+) -> ValidateCallbackResult {
+    all_valid(vec![
+        must_be_author(author, &project_meta.creator_address, "creator_address"),
+        match creator {
+            Some(creator) if creator != author => ValidateCallbackResult::Invalid(
+                "only the creator of the project can make its project meta".into(),
+            ),
+            _ => ValidateCallbackResult::Valid,
+        },
+        must_not_be_empty(&project_meta.name, "name"),
+        validate_priority_weights(&project_meta.priority_weights),
+    ])
+}
+
+// renaming a project is up to its admins, which is checked separately,
+// but it always keeps its creator
+pub fn validate_project_meta_update(
+    project_meta: &ProjectMeta,
+    original: &ProjectMeta,
+) -> ValidateCallbackResult {
+    all_valid(vec![
+        if project_meta.creator_address == original.creator_address {
+            ValidateCallbackResult::Valid
+        } else {
+            ValidateCallbackResult::Invalid("creator_address can't be changed".into())
+        },
+        must_not_be_empty(&project_meta.name, "name"),
+        validate_priority_weights(&project_meta.priority_weights),
+    ])
+}
+
+pub fn validate_priority_weights(weights: &Option<PriorityWeights>) -> ValidateCallbackResult {
+    match weights {
+        Some(weights) => all_valid(vec![
+            must_be_in_range(weights.urgency, -1.0, 1.0, "priority_weights.urgency"),
+            must_be_in_range(weights.importance, -1.0, 1.0, "priority_weights.importance"),
+            must_be_in_range(weights.impact, -1.0, 1.0, "priority_weights.impact"),
+            must_be_in_range(weights.effort, -1.0, 1.0, "priority_weights.effort"),
+        ]),
+        None => ValidateCallbackResult::Valid,
+    }
+}
+
+pub fn validate_member(member: &Member, author: &AgentPubKey) -> ValidateCallbackResult {
+    must_be_author(author, &member.address, "address")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dna_help::WrappedHeaderHash;
+
+    fn agent(byte: u8) -> AgentPubKey {
+        AgentPubKey::from_raw_36(vec![byte; 36])
+    }
+
+    fn goal_address() -> WrappedHeaderHash {
+        WrappedHeaderHash(HeaderHash::from_raw_36(vec![9; 36]))
+    }
+
+    fn goal_vote(agent_address: AgentPubKey, urgency: f64) -> GoalVote {
+        GoalVote {
+            goal_address: goal_address(),
+            urgency,
+            importance: 0.5,
+            impact: 0.5,
+            effort: 0.5,
+            agent_address: WrappedAgentPubKey(agent_address),
+            unix_timestamp: 0.0,
+        }
+    }
+
+    fn goal_comment(agent_address: AgentPubKey, content: &str) -> GoalComment {
+        GoalComment {
+            goal_address: goal_address(),
+            content: content.to_string(),
+            agent_address: WrappedAgentPubKey(agent_address),
+            unix_timestamp: 0.0,
+        }
+    }
+
+    fn goal(user_hash: AgentPubKey, user_edit_hash: Option<AgentPubKey>) -> Goal {
+        Goal {
+            content: "a goal".to_string(),
+            user_hash: WrappedAgentPubKey(user_hash),
+            user_edit_hash: user_edit_hash.map(WrappedAgentPubKey),
+            timestamp_created: 0.0,
+            timestamp_updated: None,
+            hierarchy: Hierarchy::NoHierarchy,
+            status: Status::Uncertain,
+            tags: None,
+            description: String::new(),
+            time_frame: None,
+            restored_from: None,
+        }
+    }
+
+    #[test]
+    fn goal_create_rules() {
+        assert_eq!(
+            validate_goal_create(&goal(agent(1), None), &agent(1)),
+            ValidateCallbackResult::Valid
+        );
+        assert_ne!(
+            validate_goal_create(&goal(agent(2), None), &agent(1)),
+            ValidateCallbackResult::Valid
+        );
+        // saying that you edited it doesn't make someone else's goal yours to create
+        assert_ne!(
+            validate_goal_create(&goal(agent(2), Some(agent(1))), &agent(1)),
+            ValidateCallbackResult::Valid
+        );
+    }
+
+    #[test]
+    fn goal_restore_rules() {
+        let archived = goal(agent(2), None);
+        // restored by someone else, who says so
+        assert_eq!(
+            validate_goal_restore(&goal(agent(2), Some(agent(1))), &archived, &agent(1)),
+            ValidateCallbackResult::Valid
+        );
+        assert_ne!(
+            validate_goal_restore(&goal(agent(2), Some(agent(3))), &archived, &agent(1)),
+            ValidateCallbackResult::Valid
+        );
+        // and it stays with whoever created it
+        assert_ne!(
+            validate_goal_restore(&goal(agent(1), Some(agent(1))), &archived, &agent(1)),
+            ValidateCallbackResult::Valid
+        );
+    }
+
+    #[test]
+    fn goal_update_rules() {
+        let original = goal(agent(1), None);
+        assert_eq!(
+            validate_goal_update(&goal(agent(1), Some(agent(2))), &original, &agent(2)),
+            ValidateCallbackResult::Valid
+        );
+        // editing without saying so
+        assert_ne!(
+            validate_goal_update(&goal(agent(1), None), &original, &agent(2)),
+            ValidateCallbackResult::Valid
+        );
+        assert_ne!(
+            validate_goal_update(&goal(agent(1), Some(agent(3))), &original, &agent(2)),
+            ValidateCallbackResult::Valid
+        );
+        // taking over someone else's goal
+        assert_ne!(
+            validate_goal_update(&goal(agent(2), Some(agent(2))), &original, &agent(2)),
+            ValidateCallbackResult::Valid
+        );
+    }
+
+    #[test]
+    fn goal_member_rules() {
+        let goal_member = |user_edit_hash: Option<AgentPubKey>| GoalMember {
+            goal_address: goal_address(),
+            agent_address: WrappedAgentPubKey(agent(3)),
+            user_edit_hash: user_edit_hash.map(WrappedAgentPubKey),
+            unix_timestamp: 0.0,
+        };
+        assert_eq!(
+            validate_goal_member(&goal_member(Some(agent(1))), &agent(1)),
+            ValidateCallbackResult::Valid
+        );
+        assert_ne!(
+            validate_goal_member(&goal_member(Some(agent(2))), &agent(1)),
+            ValidateCallbackResult::Valid
+        );
+        assert_ne!(
+            validate_goal_member(&goal_member(None), &agent(1)),
+            ValidateCallbackResult::Valid
+        );
+    }
+
+    #[test]
+    fn edge_rules() {
+        let edge = |parent_address: WrappedHeaderHash| Edge {
+            parent_address,
+            child_address: goal_address(),
+            randomizer: 0.0,
+        };
+        assert_eq!(
+            validate_edge(&edge(WrappedHeaderHash(HeaderHash::from_raw_36(vec![
+                8;
+                36
+            ])))),
+            ValidateCallbackResult::Valid
+        );
+        assert_eq!(
+            validate_edge(&edge(goal_address())),
+            ValidateCallbackResult::Invalid("SelfLoop".into())
+        );
+    }
+
+    #[test]
+    fn goal_vote_rules() {
+        assert_eq!(
+            validate_goal_vote(&goal_vote(agent(1), 1.0), &agent(1)),
+            ValidateCallbackResult::Valid
+        );
+        // voting on behalf of someone else
+        assert_ne!(
+            validate_goal_vote(&goal_vote(agent(2), 1.0), &agent(1)),
+            ValidateCallbackResult::Valid
+        );
+        // out of range
+        assert_ne!(
+            validate_goal_vote(&goal_vote(agent(1), 1.5), &agent(1)),
+            ValidateCallbackResult::Valid
+        );
+    }
+
+    #[test]
+    fn goal_comment_rules() {
+        assert_eq!(
+            validate_goal_comment(&goal_comment(agent(1), "nice"), &agent(1)),
+            ValidateCallbackResult::Valid
+        );
+        assert_ne!(
+            validate_goal_comment(&goal_comment(agent(1), " "), &agent(1)),
+            ValidateCallbackResult::Valid
+        );
+        assert_ne!(
+            validate_goal_comment(&goal_comment(agent(2), "nice"), &agent(1)),
+            ValidateCallbackResult::Valid
+        );
+    }
+
+    fn project_meta(creator: u8, name: &str) -> ProjectMeta {
+        ProjectMeta {
+            creator_address: WrappedAgentPubKey(agent(creator)),
+            created_at: 0.0,
+            name: name.to_string(),
+            image: None,
+            tree_mode: false,
+            priority_weights: None,
+        }
+    }
+
+    #[test]
+    fn project_meta_rules() {
+        assert_eq!(
+            validate_project_meta(&project_meta(1, "Named"), &agent(1), Some(&agent(1))),
+            ValidateCallbackResult::Valid
+        );
+        // without a creator in the properties
+        assert_eq!(
+            validate_project_meta(&project_meta(1, "Named"), &agent(1), None),
+            ValidateCallbackResult::Valid
+        );
+        assert_ne!(
+            validate_project_meta(&project_meta(2, "Named"), &agent(2), Some(&agent(1))),
+            ValidateCallbackResult::Valid
+        );
+        assert_ne!(
+            validate_project_meta(&project_meta(1, "Named"), &agent(2), Some(&agent(1))),
+            ValidateCallbackResult::Valid
+        );
+    }
+
+    #[test]
+    fn project_meta_update_rules() {
+        assert_eq!(
+            validate_project_meta_update(&project_meta(1, "Renamed"), &project_meta(1, "Named")),
+            ValidateCallbackResult::Valid
+        );
+        assert_ne!(
+            validate_project_meta_update(&project_meta(2, "Named"), &project_meta(1, "Named")),
+            ValidateCallbackResult::Valid
+        );
+        assert_ne!(
+            validate_project_meta_update(&project_meta(1, ""), &project_meta(1, "Named")),
+            ValidateCallbackResult::Valid
+        );
+    }
+
+    #[test]
+    fn priority_weights_rules() {
+        assert_eq!(
+            validate_priority_weights(&None),
+            ValidateCallbackResult::Valid
+        );
+        assert_eq!(
+            validate_priority_weights(&Some(PriorityWeights::default())),
+            ValidateCallbackResult::Valid
+        );
+        assert_ne!(
+            validate_priority_weights(&Some(PriorityWeights {
+                effort: -2.0,
+                ..PriorityWeights::default()
+            })),
+            ValidateCallbackResult::Valid
+        );
+        assert_ne!(
+            validate_priority_weights(&Some(PriorityWeights {
+                urgency: f64::NAN,
+                ..PriorityWeights::default()
+            })),
+            ValidateCallbackResult::Valid
+        );
+    }
+
+    #[test]
+    fn member_rules() {
+        let member = Member {
+            address: WrappedAgentPubKey(agent(1)),
+        };
+        assert_eq!(
+            validate_member(&member, &agent(1)),
+            ValidateCallbackResult::Valid
+        );
+        assert_ne!(
+            validate_member(&member, &agent(2)),
+            ValidateCallbackResult::Valid
+        );
+    }
+}
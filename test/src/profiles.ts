@@ -93,5 +93,217 @@ module.exports = (orchestrator) => {
       'whoami',
     )
     t.deepEqual(whoami3.entry, profile3)
+
+    // PRESENCE
+    // heartbeats write nothing, so there's nothing to wait for
+    await profilesCell.call('acorn_profiles', 'heartbeat')
+    const [presence] = await profilesCell.call(
+      'acorn_profiles',
+      'fetch_presence',
+    )
+    t.deepEqual(presence.agent_address, agent_address)
+    t.ok(presence.last_seen)
+    // we're active, so we are shown as the status we chose
+    t.equal(presence.status, 'Away')
+
+    // ONE PROFILE PER AGENT
+    // creating again edits the profile we already have
+    const profile4 = { ...profile3, first_name: 'd' }
+    const createAgain = await profilesCell.call(
+      'acorn_profiles',
+      'create_whoami',
+      profile4
+    )
+    t.deepEqual(createAgain, { entry: profile4, address: create_whoami.address })
+    await delay(2000)
+    t.deepEqual(
+      await profilesCell.call('acorn_profiles', 'fetch_agents'),
+      [profile4]
+    )
+    // and there is nothing to collapse
+    t.deepEqual(
+      await profilesCell.call('acorn_profiles', 'collapse_duplicate_profiles'),
+      createAgain
+    )
+  })
+
+  orchestrator.registerScenario('unique handles', async (s: ScenarioApi, t) => {
+    const [conductor] = await s.players([config])
+    const [[aliceHapp], [bobHapp]] = await conductor.installAgentsHapps([
+      [[profilesDnaPath]],
+      [[profilesDnaPath]],
+    ])
+    const callAlice = (fn: string, payload?: any) =>
+      aliceHapp.cells[0].call('acorn_profiles', fn, payload)
+    const callBob = (fn: string, payload?: any) =>
+      bobHapp.cells[0].call('acorn_profiles', fn, payload)
+    const newProfile = (address, handle: string) => ({
+      first_name: 'c',
+      last_name: 't',
+      handle,
+      status: 'Online',
+      avatar_url: 'test',
+      address,
+    })
+
+    const aliceAddress = await callAlice('fetch_agent_address')
+    const bobAddress = await callBob('fetch_agent_address')
+    const alice = await callAlice('create_whoami', newProfile(aliceAddress, 'alice'))
+    await delay(2000)
+
+    // handles are the same regardless of case
+    t.deepEqual(await callBob('fetch_agent_by_handle', 'ALICE'), alice)
+    try {
+      await callBob('create_whoami', newProfile(bobAddress, 'Alice'))
+      t.fail('a taken handle should be refused')
+    } catch (e) {
+      t.equal(true, e.data.data.includes('the handle Alice is already taken'))
+    }
+
+    // once alice moves to another handle, the old one is free
+    await callAlice('update_whoami', {
+      entry: newProfile(aliceAddress, 'alice2'),
+      address: alice.address,
+    })
+    await delay(2000)
+    t.equal(await callBob('fetch_agent_by_handle', 'alice'), null)
+    const bob = await callBob('create_whoami', newProfile(bobAddress, 'alice'))
+    await delay(2000)
+    t.deepEqual(await callAlice('fetch_agent_by_handle', 'alice'), bob)
+
+    // SEARCH
+    // alice's old handle is no longer found, but her new one is
+    t.deepEqual(
+      await callAlice('search_profiles', { query: 'ALI', limit: 10 }),
+      [bob.entry, newProfile(aliceAddress, 'alice2')]
+    )
+    t.deepEqual(
+      await callAlice('search_profiles', { query: 'alice2', limit: 10 }),
+      [newProfile(aliceAddress, 'alice2')]
+    )
+    t.equal(
+      (await callAlice('search_profiles', { query: 'a', limit: 1 })).length,
+      1
+    )
+    t.deepEqual(
+      await callAlice('search_profiles', { query: 'nobody', limit: 10 }),
+      []
+    )
+
+    // a later change that keeps the handle and names still finds her
+    // latest profile by handle and by search
+    const aliceAway = { ...newProfile(aliceAddress, 'alice2'), status: 'Away' }
+    await callAlice('update_whoami', { entry: aliceAway, address: alice.address })
+    await delay(2000)
+    t.deepEqual(
+      (await callBob('fetch_agent_by_handle', 'alice2')).entry,
+      aliceAway
+    )
+    t.deepEqual(
+      await callBob('search_profiles', { query: 'alice2', limit: 10 }),
+      [aliceAway]
+    )
+
+    // FETCH PROFILE(S)
+    const aliceLatest = {
+      entry: aliceAway,
+      address: alice.address,
+    }
+    t.deepEqual(await callBob('fetch_profile', aliceAddress), aliceLatest)
+    t.deepEqual(
+      await callBob('fetch_profiles', [bobAddress, aliceAddress]),
+      [bob, aliceLatest]
+    )
+  })
+
+  orchestrator.registerScenario('avatars', async (s: ScenarioApi, t) => {
+    const [conductor] = await s.players([config])
+    const [[profileHapp]] = await conductor.installAgentsHapps([[[profilesDnaPath]]])
+    const [profilesCell] = profileHapp.cells
+    const call = (fn: string, payload?: any) =>
+      profilesCell.call('acorn_profiles', fn, payload)
+    const agentAddress = await call('fetch_agent_address')
+
+    t.equal(await call('fetch_avatar', agentAddress), null)
+
+    // the PNG signature, and then enough to take more than one chunk
+    const png = Buffer.concat([
+      Buffer.from([0x89, 0x50, 0x4e, 0x47, 0x0d, 0x0a, 0x1a, 0x0a]),
+      Buffer.alloc(70 * 1024, 1),
+    ])
+    const manifest = await call('upload_avatar', {
+      bytes: png,
+      mime_type: 'image/png',
+    })
+    t.equal(manifest.entry.size, png.length)
+    t.equal(manifest.entry.chunks.length, 2)
+    await delay(2000)
+
+    const avatar = await call('fetch_avatar', agentAddress)
+    t.equal(avatar.mime_type, 'image/png')
+    t.ok(Buffer.from(avatar.bytes).equals(png))
+
+    try {
+      await call('upload_avatar', { bytes: png, mime_type: 'image/jpeg' })
+      t.fail('an avatar which is not what it says should be refused')
+    } catch (e) {
+      t.equal(true, e.data.data.includes('the avatar is not a image/jpeg file'))
+    }
+  })
+
+  orchestrator.registerScenario('profile fields', async (s: ScenarioApi, t) => {
+    const [conductor] = await s.players([config])
+    const [[profileHapp]] = await conductor.installAgentsHapps([[[profilesDnaPath]]])
+    const [profilesCell] = profileHapp.cells
+    const call = (fn: string, payload?: any) =>
+      profilesCell.call('acorn_profiles', fn, payload)
+    const address = await call('fetch_agent_address')
+    const profile = {
+      first_name: 'c',
+      last_name: 't',
+      handle: 'ct',
+      status: 'Online',
+      avatar_url: '',
+      address,
+    }
+
+    // the newer fields need the newer schema version
+    try {
+      await call('create_whoami', { ...profile, bio: 'hello' })
+      t.fail('a bio on a version 1 profile should be refused')
+    } catch (e) {
+      t.equal(true, e.data.data.includes('need schema_version 2'))
+    }
+
+    const fullProfile = {
+      ...profile,
+      schema_version: 2,
+      bio: 'hello',
+      pronouns: 'they/them',
+      timezone: 'Europe/Berlin',
+      fields: { team: 'design' },
+    }
+    const created = await call('create_whoami', fullProfile)
+    t.deepEqual(created.entry, fullProfile)
+    await delay(2000)
+    t.deepEqual((await call('whoami')).entry, fullProfile)
+
+    try {
+      await call('update_whoami', {
+        entry: { ...fullProfile, bio: 'a'.repeat(2001) },
+        address: created.address,
+      })
+      t.fail('a bio which is too long should be refused')
+    } catch (e) {
+      t.equal(true, e.data.data.includes('bio can be at most 2000 characters'))
+    }
+
+    // FORGET ME
+    t.deepEqual(await call('delete_whoami'), created.address)
+    await delay(2000)
+    t.equal(await call('whoami'), null)
+    t.deepEqual(await call('fetch_agents'), [])
+    t.equal(await call('fetch_agent_by_handle', 'ct'), null)
+    t.deepEqual(await call('search_profiles', { query: 'ct', limit: 10 }), [])
   })
 }
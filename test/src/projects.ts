@@ -68,6 +68,15 @@ async function runPlainCrudTest({
   tape.equal(fetchResult.length, 1)
   tape.deepEqual(fetchResult[0], createResult)
 
+  // READ A PAGE
+  const fetchPageResult = await callAlice(`fetch_${entryType}s_page`, {
+    cursor: null,
+    limit: 10,
+    since: null,
+    until: null,
+  })
+  tape.deepEqual(fetchPageResult, { entries: [createResult], next_cursor: null })
+
   // UDPATE
   const entryUpdate = {
     ...baseEntry,
@@ -103,6 +112,20 @@ module.exports = (orchestrator: Orchestrator<null>) => {
       tape.equal(1, result.length)
       tape.ok(result[0].address)
       tape.equal(result[0].address.length, sampleResult.length)
+
+      // only Admins and Owners can remove members
+      try {
+        await callAlice('remove_member', agentAddress)
+        tape.fail('an Editor should not be able to remove members')
+      } catch (e) {
+        tape.equal(true, e.data.data.includes('"type":"Unauthorized"'))
+      }
+
+      // but anyone can leave
+      const leaveResult = await callAlice('leave_project')
+      tape.deepEqual(leaveResult, result[0])
+      await delay(1000)
+      tape.deepEqual(await callAlice('fetch_members'), [])
     }
   )
 
@@ -125,7 +148,10 @@ module.exports = (orchestrator: Orchestrator<null>) => {
       tape.deepEqual(fetchGoalsResult[0], createGoalResult)
 
       // UDPATE
-      const updatedGoal = newGoal(agentAddress, 'Updated Goal Content')
+      const updatedGoal = {
+        ...newGoal(agentAddress, 'Updated Goal Content'),
+        user_edit_hash: agentAddress,
+      }
       const updateGoalResult = await callAlice('update_goal', {
         entry: updatedGoal,
         address: createGoalResult.address,
@@ -142,6 +168,17 @@ module.exports = (orchestrator: Orchestrator<null>) => {
       // values
       tape.deepEqual(fetchGoals2Result[0], updateGoalResult)
 
+      // HISTORY
+      const historyResult = await callAlice(
+        'history_of_goal',
+        createGoalResult.address
+      )
+      tape.equal(historyResult.entries.length, 2)
+      tape.deepEqual(historyResult.entries[0].entry, goal)
+      tape.deepEqual(historyResult.entries[1].entry, updatedGoal)
+      tape.deepEqual(historyResult.entries[1].author, agentAddress)
+      tape.deepEqual(historyResult.members, [[], []])
+
       // ARCHIVE / DELETE
       const archiveGoalResult = await callAlice(
         'archive_goal',
@@ -224,6 +261,23 @@ module.exports = (orchestrator: Orchestrator<null>) => {
           creator_address: agentAddress,
         }
       )
+      // each of those can be found through the goal's own index
+      const goalVotesForGoal = await callAlice(
+        'fetch_goal_votes_for_goal',
+        createGoalWithEdgeResult.goal.address
+      )
+      tape.equal(goalVotesForGoal.length, 1)
+      tape.deepEqual(goalVotesForGoal[0].address, goalVoteAddress)
+      const edgesForGoal = await callAlice(
+        'fetch_edges_for_goal',
+        createGoalWithEdgeResult.goal.address
+      )
+      tape.equal(edgesForGoal.length, 1)
+      tape.deepEqual(
+        edgesForGoal[0].address,
+        createGoalWithEdgeResult.maybe_edge.address
+      )
+
       const archiveGoalFullyResult = await callAlice(
         'archive_goal_fully',
         createGoalWithEdgeResult.goal.address
@@ -237,6 +291,67 @@ module.exports = (orchestrator: Orchestrator<null>) => {
         archived_goal_members: [goalMemberAddress],
         archived_entry_points: [entryPointAddress],
       })
+
+      // TRASH AND RESTORE GOAL FULLY
+      await delay(2000)
+      const trash = await callAlice('fetch_trash')
+      tape.equal(trash.length, 1)
+      tape.deepEqual(trash[0].archive, archiveGoalFullyResult)
+      tape.equal(trash[0].goal.content, 'Test Goal With Edge')
+
+      const restoreGoalFullyResult = await callAlice(
+        'restore_goal_fully',
+        trash[0].archive.address
+      )
+      const restoredAddress = restoreGoalFullyResult.goal.address
+      tape.notDeepEqual(restoredAddress, archiveGoalFullyResult.address)
+      tape.equal(restoreGoalFullyResult.goal.entry.content, 'Test Goal With Edge')
+      tape.equal(restoreGoalFullyResult.restored_edges.length, 1)
+      tape.deepEqual(
+        restoreGoalFullyResult.restored_edges[0].entry.child_address,
+        restoredAddress
+      )
+      tape.deepEqual(
+        restoreGoalFullyResult.restored_edges[0].entry.parent_address,
+        createGoalWithNoEdgeResult.goal.address
+      )
+      for (const type of [
+        'goal_members',
+        'goal_votes',
+        'goal_comments',
+        'entry_points',
+      ]) {
+        tape.equal(restoreGoalFullyResult[`restored_${type}`].length, 1)
+        tape.deepEqual(
+          restoreGoalFullyResult[`restored_${type}`][0].entry.goal_address,
+          restoredAddress
+        )
+      }
+      tape.deepEqual(restoreGoalFullyResult.not_restored, [])
+
+      // once restored, it leaves the trash, and can't be restored twice
+      await delay(2000)
+      tape.deepEqual(await callAlice('fetch_trash'), [])
+      try {
+        await callAlice(
+          'restore_goal_fully',
+          archiveGoalFullyResult.address
+        )
+        tape.fail('restoring twice should fail')
+      } catch (e) {
+        tape.equal(true, e.data.data.includes('already restored'))
+      }
+
+      // mistyped enums are refused, rather than read as some other value
+      try {
+        await callAlice('create_goal', {
+          ...newGoal(agentAddress, 'Typo'),
+          hierarchy: 'Rooot',
+        })
+        tape.fail('an unknown hierarchy should be refused')
+      } catch (e) {
+        tape.equal(true, JSON.stringify(e).includes('unknown hierarchy'))
+      }
     }
   )
 
@@ -270,6 +385,95 @@ module.exports = (orchestrator: Orchestrator<null>) => {
         callAlice,
         tape,
       })
+
+      // edges which would break the tree are refused
+      const edge1To2 = {
+        parent_address: createGoal1Result.address,
+        child_address: createGoal2Result.address,
+        randomizer: 123,
+      }
+      await callAlice('create_edge', edge1To2)
+      const refusedEdges = [
+        {
+          error: 'SelfLoop',
+          edge: { ...edge1To2, child_address: createGoal1Result.address },
+        },
+        { error: 'DuplicateEdge', edge: { ...edge1To2, randomizer: 456 } },
+        {
+          error: 'CycleDetected',
+          edge: {
+            parent_address: createGoal2Result.address,
+            child_address: createGoal1Result.address,
+            randomizer: 789,
+          },
+        },
+      ]
+      for (const { error, edge } of refusedEdges) {
+        try {
+          await callAlice('create_edge', edge)
+          tape.fail(`expected create_edge to fail with ${error}`)
+        } catch (e) {
+          tape.equal(true, e.data.data.includes(error))
+        }
+      }
+    }
+  )
+
+  orchestrator.registerScenario(
+    'tree mode',
+    async (scenario: ScenarioApi, tape) => {
+      const { callAlice } = await setup(scenario)
+      const [{ address: agentAddress }] = await callAlice('fetch_members')
+
+      await callAlice('create_project_meta', {
+        creator_address: agentAddress,
+        created_at: Date.now(),
+        name: 'Tree Project',
+        image: '',
+        tree_mode: true,
+      })
+      const { address: goal1Address } = await callAlice(
+        'create_goal',
+        newGoal(agentAddress, 'Test Goal 1')
+      )
+      const { address: goal2Address } = await callAlice(
+        'create_goal',
+        newGoal(agentAddress, 'Test Goal 2')
+      )
+      const { address: goal3Address } = await callAlice(
+        'create_goal',
+        newGoal(agentAddress, 'Test Goal 3')
+      )
+      const { address: edgeAddress } = await callAlice('create_edge', {
+        parent_address: goal1Address,
+        child_address: goal3Address,
+        randomizer: 1,
+      })
+
+      // a second parent is refused
+      try {
+        await callAlice('create_edge', {
+          parent_address: goal2Address,
+          child_address: goal3Address,
+          randomizer: 2,
+        })
+        tape.fail('expected create_edge to fail with MultipleParents')
+      } catch (e) {
+        tape.equal(true, e.data.data.includes('MultipleParents'))
+      }
+
+      // but the parent can be swapped
+      const reparentResult = await callAlice('reparent_goal', {
+        goal_address: goal3Address,
+        new_parent_address: goal2Address,
+      })
+      tape.deepEqual(reparentResult.archived_edges, [edgeAddress])
+      tape.deepEqual(reparentResult.edge.entry.parent_address, goal2Address)
+      tape.deepEqual(reparentResult.edge.entry.child_address, goal3Address)
+
+      const fetchEdgesResult = await callAlice('fetch_edges')
+      tape.equal(fetchEdgesResult.length, 1)
+      tape.deepEqual(fetchEdgesResult[0], reparentResult.edge)
     }
   )
 
@@ -392,6 +596,86 @@ module.exports = (orchestrator: Orchestrator<null>) => {
     }
   )
 
+  orchestrator.registerScenario(
+    'goal priorities',
+    async (scenario: ScenarioApi, tape) => {
+      const { callAlice } = await setup(scenario)
+      const [{ address: agentAddress }] = await callAlice('fetch_members')
+
+      const { address: goal1Address } = await callAlice(
+        'create_goal',
+        newGoal(agentAddress, 'Test Goal 1')
+      )
+      const { address: goal2Address } = await callAlice(
+        'create_goal',
+        newGoal(agentAddress, 'Test Goal 2')
+      )
+      const vote = (goalAddress, urgency, importance, impact, effort) =>
+        callAlice('create_goal_vote', {
+          goal_address: goalAddress,
+          urgency,
+          importance,
+          impact,
+          effort,
+          agent_address: agentAddress,
+          unix_timestamp: Date.now(),
+        })
+      await vote(goal1Address, 1, 1, 1, 0)
+      await vote(goal1Address, 0.5, 0.5, 0.5, 0.5)
+      await vote(goal2Address, 0, 0, 0, 1)
+
+      // without a project meta, the default weights count effort against a goal
+      const priorities = await callAlice('fetch_goal_priorities')
+      tape.equal(priorities.length, 2)
+      tape.deepEqual(priorities[0], {
+        goal_address: goal1Address,
+        vote_count: 2,
+        urgency: { mean: 0.75, median: 0.75 },
+        importance: { mean: 0.75, median: 0.75 },
+        impact: { mean: 0.75, median: 0.75 },
+        effort: { mean: 0.25, median: 0.25 },
+        score: 2,
+      })
+      tape.deepEqual(priorities[1].goal_address, goal2Address)
+      tape.equal(priorities[1].vote_count, 1)
+      tape.equal(priorities[1].score, -1)
+
+      const projectMeta = {
+        creator_address: agentAddress,
+        created_at: Date.now(),
+        name: 'Weighted Project',
+        image: '',
+        tree_mode: false,
+      }
+      try {
+        await callAlice('create_project_meta', {
+          ...projectMeta,
+          priority_weights: { urgency: 1, importance: 1, impact: 1, effort: 2 },
+        })
+        tape.fail('weights outside of -1 to 1 should be refused')
+      } catch (e) {
+        tape.equal(
+          true,
+          JSON.stringify(e).includes('priority_weights.effort must be between')
+        )
+      }
+
+      // the weights on the project meta are used for everyone
+      await callAlice('create_project_meta', {
+        ...projectMeta,
+        priority_weights: { urgency: 0, importance: 0, impact: 0, effort: 1 },
+      })
+      const reweighted = await callAlice('fetch_goal_priorities')
+      tape.deepEqual(
+        reweighted.map(({ goal_address, score }) => [goal_address, score]),
+        [
+          [goal2Address, 1],
+          [goal1Address, 0.25],
+        ]
+      )
+    }
+  )
+
   orchestrator.registerScenario(
     'project_meta api',
     async (scenario: ScenarioApi, tape) => {
@@ -410,7 +694,7 @@ module.exports = (orchestrator: Orchestrator<null>) => {
           creator_address: agentAddress,
           created_at: Date.now(),
           image: '',
-          passphrase: 'pinky-stomp-tuffle-waffle',
+          tree_mode: false,
         },
         callAlice,
         tape,
@@ -420,8 +704,203 @@ module.exports = (orchestrator: Orchestrator<null>) => {
       try {
         await callAlice('fetch_project_meta')
       } catch (e) {
+        tape.equal(true, e.data.data.includes('"type":"NotFound"'))
         tape.equal(true, e.data.data.includes('no project meta exists'))
       }
+
+      // there can only be one project meta at a time
+      const projectMeta = {
+        creator_address: agentAddress,
+        created_at: Date.now(),
+        name: 'The Project',
+        image: '',
+        tree_mode: false,
+      }
+      const createResult = await callAlice('create_project_meta', projectMeta)
+      try {
+        await callAlice('create_project_meta', {
+          ...projectMeta,
+          name: 'A Competing Project',
+        })
+        tape.fail('a second project meta should be refused')
+      } catch (e) {
+        tape.equal(true, e.data.data.includes('"type":"ValidationFailed"'))
+        tape.equal(true, e.data.data.includes('a project meta already exists'))
+      }
+      tape.deepEqual(await callAlice('fetch_project_meta'), createResult)
+    }
+  )
+  orchestrator.registerScenario(
+    'fetch_changes_since',
+    async (scenario: ScenarioApi, tape) => {
+      const { callAlice, agentAddress } = await setup(scenario)
+      const createGoalResult = await callAlice(
+        'create_goal',
+        newGoal(agentAddress, 'Before')
+      )
+      await delay(1000)
+      const since = Date.now() / 1000
+      await callAlice('update_goal', {
+        entry: {
+          ...newGoal(agentAddress, 'After'),
+          user_edit_hash: agentAddress,
+        },
+        address: createGoalResult.address,
+      })
+      await callAlice('archive_goal', createGoalResult.address)
+      await delay(2000)
+
+      const changes = await callAlice('fetch_changes_since', since)
+      // the create happened before `since`, so only the update and delete are listed
+      tape.deepEqual(
+        changes.map(signal => [signal.entry_type, signal.action]),
+        [
+          ['goal', 'Update'],
+          ['goal', 'Delete'],
+        ]
+      )
+      tape.equal(changes[0].data.entry.content, 'After')
+      tape.deepEqual(changes[1].data, createGoalResult.address)
+    }
+  )
+  orchestrator.registerScenario(
+    'roles',
+    async (scenario: ScenarioApi, tape) => {
+      const { callAlice, agentAddress } = await setup(scenario)
+
+      // until there is a project meta, nobody owns the project,
+      // and everyone is an Editor, who can't hand out roles
+      tape.deepEqual(await callAlice('fetch_member_roles'), [
+        { agent_address: agentAddress, role: 'Editor' },
+      ])
+      try {
+        await callAlice('assign_role', {
+          agent_address: agentAddress,
+          role: 'Admin',
+        })
+        tape.fail('an Editor should not be able to assign roles')
+      } catch (e) {
+        tape.equal(true, e.data.data.includes('"type":"Unauthorized"'))
+      }
+
+      // whoever makes the project meta is its Owner
+      await callAlice('create_project_meta', {
+        creator_address: agentAddress,
+        created_at: Date.now(),
+        name: 'The Project',
+        image: '',
+        tree_mode: false,
+      })
+      tape.deepEqual(await callAlice('fetch_member_roles'), [
+        { agent_address: agentAddress, role: 'Owner' },
+      ])
+    }
+  )
+  orchestrator.registerScenario(
+    'invitations',
+    async (scenario: ScenarioApi, tape) => {
+      const { callAlice, agentAddress } = await setup(scenario)
+      // be the Owner, who can manage invitations
+      await callAlice('create_project_meta', {
+        creator_address: agentAddress,
+        created_at: Date.now(),
+        name: 'The Project',
+        image: '',
+        tree_mode: false,
+      })
+
+      const { invitation, code } = await callAlice('create_invitation', {
+        invitee: null,
+        role: 'Viewer',
+        expires_at: null,
+        max_uses: 1,
+      })
+      tape.deepEqual(code.invitation_address, invitation.address)
+      // the code itself is never stored
+      tape.notEqual(invitation.entry.code_hash, code.code)
+      // and without an invitee, there's nobody to approve it for yet
+      tape.equal(code.approval, null)
+
+      // a wrong code is refused
+      try {
+        await callAlice('redeem_invitation', { ...code, code: 'guess' })
+        tape.fail('a wrong code should be refused')
+      } catch (e) {
+        tape.equal(true, e.data.data.includes('the invitation code is wrong'))
+      }
+
+      const redemption = await callAlice('redeem_invitation', code)
+      tape.deepEqual(redemption.entry.agent_address, agentAddress)
+      tape.equal(JSON.stringify(redemption.entry).includes(code.code), false)
+      await delay(1000)
+      const [listing] = await callAlice('list_invitations')
+      tape.deepEqual(listing.invitation, invitation)
+      tape.deepEqual(listing.redemptions, [redemption])
+
+      // it was single use
+      try {
+        await callAlice('redeem_invitation', code)
+        tape.fail('a used up invitation should be refused')
+      } catch (e) {
+        tape.equal(true, e.data.data.includes('the invitation has been used up'))
+      }
+
+      await callAlice('revoke_invitation', invitation.address)
+      await delay(1000)
+      tape.deepEqual(await callAlice('list_invitations'), [])
+    }
+  )
+  orchestrator.registerScenario(
+    'invitation redemptions cannot be replayed',
+    async (scenario: ScenarioApi, tape) => {
+      const [conductor] = await scenario.players([config])
+      const [[aliceHapp], [bobHapp]] = await conductor.installAgentsHapps([
+        [[projectsDnaPath]],
+        [[projectsDnaPath]],
+      ])
+      const callAlice = (fn: string, payload?: any) =>
+        aliceHapp.cells[0].call(ZOME, fn, payload)
+      const callBob = (fn: string, payload?: any) =>
+        bobHapp.cells[0].call(ZOME, fn, payload)
+      await callAlice('create_project_meta', {
+        creator_address: aliceHapp.agent,
+        created_at: Date.now(),
+        name: 'The Project',
+        image: '',
+        tree_mode: false,
+      })
+      const { code } = await callAlice('create_invitation', {
+        invitee: null,
+        role: 'Admin',
+        expires_at: null,
+        max_uses: 2,
+      })
+      const redemption = await callAlice('redeem_invitation', code)
+      await delay(2000)
+
+      // Bob can read Alice's redemption from the DHT, and has the code,
+      // but the approval in it was made for Alice only
+      try {
+        await callBob('redeem_invitation', {
+          ...code,
+          approval: redemption.entry.approval,
+        })
+        tape.fail('a redemption made for someone else should be refused')
+      } catch (e) {
+        tape.equal(
+          true,
+          JSON.stringify(e).includes(
+            "the invitation wasn't approved for this agent by whoever made it"
+          )
+        )
+      }
+      await delay(1000)
+      // so the invitation didn't make Bob an Admin
+      const roles = await callAlice('fetch_member_roles')
+      tape.deepEqual(
+        roles.filter(memberRole => memberRole.role === 'Admin'),
+        []
+      )
     }
   )
 }